@@ -0,0 +1,136 @@
+//! Drives a particle system from a minimal event loop of its own, without the
+//! bundled `App`.
+
+use {
+    particle_system::{
+        camera::Camera,
+        particle_system::{
+            BlendMode, ParticleEmissionMode, ParticleEmissionShape, ParticlePrecision,
+            ParticleSystem, ParticleSystemInfo,
+        },
+        renderer::Renderer,
+        timer::Timer,
+    },
+    std::sync::Arc,
+    winit::{
+        application::ApplicationHandler,
+        event::WindowEvent,
+        event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+        window::{Window, WindowId},
+    },
+};
+
+struct Scene {
+    window: Arc<Window>,
+    renderer: Renderer,
+    particle_system: ParticleSystem,
+    camera: Camera,
+    timer: Timer,
+}
+
+#[derive(Default)]
+struct Embed {
+    scene: Option<Scene>,
+}
+
+impl ApplicationHandler for Embed {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let window = match event_loop.create_window(Window::default_attributes()) {
+            Ok(window) => Arc::new(window),
+            Err(e) => {
+                eprintln!("Failed to create window: {e:?}");
+                event_loop.exit();
+                return;
+            }
+        };
+
+        let info = ParticleSystemInfo {
+            position: glam::Vec3::ZERO,
+            shape: ParticleEmissionShape::Sphere,
+            mode: ParticleEmissionMode::Continuous(10000),
+            lifetime: 5.0,
+            lifetime_variance: 0.0,
+            precision: ParticlePrecision::Full,
+            depth_bias: wgpu::DepthBiasState::default(),
+            blend_mode: BlendMode::Additive,
+        };
+
+        let renderer = match pollster::block_on(
+            Renderer::builder()
+                .storage_buffer_size(ParticleSystem::required_buffer_size(&info))
+                .build(window.clone()),
+        ) {
+            Ok(renderer) => renderer,
+            Err(e) => {
+                eprintln!("Failed to create renderer: {e}");
+                event_loop.exit();
+                return;
+            }
+        };
+
+        let particle_system =
+            ParticleSystem::new(renderer.device(), renderer.target_format(), info);
+
+        let size = window.inner_size();
+        let camera = Camera::new(
+            glam::vec3(0.0, 0.0, 20.0),
+            glam::Vec3::ZERO,
+            glam::Vec3::Y,
+            size.width as f32 / size.height.max(1) as f32,
+            90.0f32.to_radians(),
+            0.1,
+            1000.0,
+        );
+
+        self.scene = Some(Scene {
+            window,
+            renderer,
+            particle_system,
+            camera,
+            timer: Timer::new(),
+        });
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        let Some(scene) = &mut self.scene else {
+            return;
+        };
+
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::Resized(size) => {
+                scene.camera.resize(size.width, size.height);
+                scene.renderer.resize(size.width, size.height);
+            }
+            WindowEvent::RedrawRequested => {
+                let delta_time = scene.timer.tick();
+
+                if let Ok(mut frame) = scene.renderer.begin_frame() {
+                    scene
+                        .particle_system
+                        .set_view_proj(scene.camera.view_proj());
+                    scene.particle_system.update(&mut frame, delta_time);
+                    scene.renderer.end_frame(frame);
+                }
+
+                scene.window.request_redraw();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn main() {
+    let event_loop = match EventLoop::new() {
+        Ok(event_loop) => event_loop,
+        Err(e) => {
+            eprintln!("Failed to create event loop: {e:?}");
+            return;
+        }
+    };
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    if let Err(e) = event_loop.run_app(&mut Embed::default()) {
+        eprintln!("Event loop error: {e:?}");
+    }
+}
@@ -0,0 +1,46 @@
+struct UpdateUniforms {
+    elapsed_time: f32,
+    delta_time: f32,
+    repulsion_radius: f32,
+    repulsion_strength: f32,
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+    // 0: unbounded, 1: wrap around
+    bounds_mode: u32,
+    // Non-zero to count active invocations in debug_counters
+    debug: u32,
+}
+
+// Particle and PackedParticle come from the particle.wgsl prelude
+
+@group(0) @binding(0) var<uniform> uniforms: UpdateUniforms;
+@group(0) @binding(1) var<storage, read> particles: array<PackedParticle>;
+@group(0) @binding(2) var<storage, read> indirect_buffer: array<u32>;
+@group(0) @binding(3) var<storage, read_write> cell_heads: array<atomic<u32>>;
+@group(0) @binding(4) var<storage, read_write> next: array<u32>;
+
+fn hash_cell(cell: vec3<i32>) -> u32 {
+    let c = bitcast<vec3<u32>>(cell);
+    let h = (c.x * 73856093u) ^ (c.y * 19349663u) ^ (c.z * 83492791u);
+    return h % arrayLength(&cell_heads);
+}
+
+// Pushes every live particle onto the linked list of its grid cell, entries
+// are stored as index + 1 so that a cleared head means an empty cell
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    let count = min(indirect_buffer[1], arrayLength(&particles));
+    if (index >= count) {
+        return;
+    }
+
+    let particle = unpack_particle(particles[index]);
+    if (particle.age >= particle.lifetime) {
+        next[index] = 0u;
+        return;
+    }
+
+    let cell = vec3<i32>(floor(particle.position.xyz / uniforms.repulsion_radius));
+    next[index] = atomicExchange(&cell_heads[hash_cell(cell)], index + 1u);
+}
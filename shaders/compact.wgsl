@@ -1,19 +1,16 @@
-struct Particle {
-    position: vec4<f32>,
-    velocity: vec4<f32>,
-    mass: f32,
-    lifetime: f32,
-    age: f32,
-}
+// Particle and PackedParticle come from the particle.wgsl prelude
 
-@group(0) @binding(0) var<storage, read> particles_in: array<Particle>;
-@group(0) @binding(1) var<storage, read_write> particles_out: array<Particle>;
+@group(0) @binding(0) var<storage, read> particles_in: array<PackedParticle>;
+@group(0) @binding(1) var<storage, read_write> particles_out: array<PackedParticle>;
 @group(0) @binding(2) var<storage, read_write> indirect_buffer: array<atomic<u32>>;
+// Particle count before compaction, then after it
+@group(0) @binding(3) var<storage, read> counts: array<u32>;
 
 @compute @workgroup_size(256)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index = global_id.x;
-    if (index >= arrayLength(&particles_in)) {
+    // Slots past last frame's count hold stale copies of moved particles
+    if (index >= arrayLength(&particles_in) || index >= counts[0]) {
         return;
     }
 
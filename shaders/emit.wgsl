@@ -1,22 +1,60 @@
 struct EmitUniforms {
     position: vec4<f32>,
     count: u32,
+    // 0: point, 1: sphere, 2: cube, 3: line, 4: helix, 5: spiral, 6: cone, 7: disk
     shape: u32,
     lifetime: f32,
     elapsed_time: f32,
+    // Particles emitted before this batch, offsets the sampling sequence
+    sample_base: u32,
+    shell_thickness: f32,
+    distribution: u32,
+    sigma: f32,
+    tint: vec4<f32>,
+    line_start: vec4<f32>,
+    line_end: vec4<f32>,
+    extents: vec4<f32>,
+    // 0: hash random, 1: Halton sequence
+    sampling: u32,
+    // Non-zero to count active invocations in debug_counters
+    debug: u32,
+    // Non-zero to spread particles from previous_position to position
+    interpolate: u32,
+    age_stagger: f32,
+    previous_position: vec4<f32>,
+    // Rotation of the emission shape as a quaternion
+    orientation: vec4<f32>,
+    // Helix and spiral: radius, pitch, turns, phase in radians
+    curve: vec4<f32>,
+    temperature: f32,
+    // Non-zero to color particles from projection_texture
+    project_color: u32,
+    seed: u32,
+    // Lifetimes are spread over lifetime +- lifetime_variance
+    lifetime_variance: f32,
+    projection_view_proj: mat4x4<f32>,
+    // Cone: axis scaled by the speed, half angle in radians
+    cone: vec4<f32>,
+    // Disk: inner and outer radius, then the unit normal
+    disk_radii: vec4<f32>,
+    disk_normal: vec4<f32>,
+    // Speed replacing the shape's own, spread over initial_speed +- speed_variance
+    initial_speed: f32,
+    speed_variance: f32,
+    // Non-zero to launch particles at initial_speed
+    override_speed: u32,
 }
 
-struct Particle {
-    position: vec4<f32>,
-    velocity: vec4<f32>,
-    mass: f32,
-    lifetime: f32,
-    age: f32,
-}
+// Particle and PackedParticle come from the particle.wgsl prelude
 
 @group(0) @binding(0) var<uniform> uniforms: EmitUniforms;
-@group(0) @binding(1) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read_write> particles: array<PackedParticle>;
+// Indirect draw args, then the id of the next particle
 @group(0) @binding(2) var<storage, read_write> indirect_buffer: array<atomic<u32>>;
+// Active emit invocations, then active update invocations
+@group(0) @binding(3) var<storage, read_write> debug_counters: array<atomic<u32>>;
+@group(0) @binding(4) var projection_texture: texture_2d<f32>;
+@group(0) @binding(5) var projection_sampler: sampler;
 
 fn hash(x: u32) -> u32 {
     var s = x;
@@ -28,8 +66,37 @@ fn hash(x: u32) -> u32 {
     return s;
 }
 
+// Position in the emission order and next Halton dimension of this invocation
+var<private> sample_index: u32;
+var<private> sample_dimension: u32;
+
+// Radical inverse of `index` in `base`
+fn halton(index: u32, base: u32) -> f32 {
+    var i = index;
+    var f = 1.0;
+    var result = 0.0;
+
+    while (i > 0u) {
+        f /= f32(base);
+        result += f * f32(i % base);
+        i /= base;
+    }
+
+    return result;
+}
+
 fn random_float(state: ptr<function, u32>) -> f32 {
     *state = hash(*state);
+
+    // Each draw takes the next dimension of the Halton sequence, falling back
+    // to the hash once the primes run out
+    if (uniforms.sampling == 1u && sample_dimension < 8u) {
+        let primes = array<u32, 8>(2u, 3u, 5u, 7u, 11u, 13u, 17u, 19u);
+        let value = halton(sample_index, primes[sample_dimension]);
+        sample_dimension++;
+        return value;
+    }
+
     return f32(*state) / f32(0xFFFFFFFFu);
 }
 
@@ -51,6 +118,29 @@ fn random_on_sphere(state: ptr<function, u32>) -> vec3<f32> {
     return vec3<f32>(x, y, z);
 }
 
+// Box-Muller transform, returns two independent standard normal samples
+fn random_gaussian(state: ptr<function, u32>) -> vec2<f32> {
+    let u1 = max(random_float(state), 1e-7);
+    let u2 = random_float(state);
+
+    let r = sqrt(-2.0 * log(u1));
+    let theta = u2 * 2.0 * 3.14159265359;
+
+    return vec2<f32>(r * cos(theta), r * sin(theta));
+}
+
+fn random_gaussian3(state: ptr<function, u32>) -> vec3<f32> {
+    let xy = random_gaussian(state);
+    let z = random_gaussian(state).x;
+
+    return vec3<f32>(xy, z);
+}
+
+fn rotate(q: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+    let t = 2.0 * cross(q.xyz, v);
+    return v + q.w * t + cross(q.xyz, t);
+}
+
 fn random_on_cube(state: ptr<function, u32>) -> vec3<f32> {
     let face = u32(random_float(state) * 6.0);
     let u = random_float(state) * 2.0 - 1.0;
@@ -67,6 +157,62 @@ fn random_on_cube(state: ptr<function, u32>) -> vec3<f32> {
     }
 }
 
+// Direction at most `angle` radians away from `axis`, uniform over the solid
+// angle and as long as `axis`
+fn random_in_cone(state: ptr<function, u32>, axis: vec3<f32>, angle: f32) -> vec3<f32> {
+    let speed = length(axis);
+    if (speed == 0.0) {
+        return vec3<f32>(0.0);
+    }
+
+    let basis = orthonormal_basis(axis / speed);
+    let cos_theta = mix(1.0, cos(angle), random_float(state));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = random_float(state) * 2.0 * 3.14159265359;
+
+    return basis * vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta) * speed;
+}
+
+// Point on the ring between `inner` and `outer` around `normal`, the radius
+// drawn with a square root so the density is even over the area
+fn random_on_disk(state: ptr<function, u32>, inner: f32, outer: f32, normal: vec3<f32>) -> vec3<f32> {
+    let basis = orthonormal_basis(normal);
+    let radius = sqrt(mix(inner * inner, outer * outer, random_float(state)));
+    let phi = random_float(state) * 2.0 * 3.14159265359;
+
+    return basis * vec3(cos(phi) * radius, sin(phi) * radius, 0.0);
+}
+
+// Columns are two unit vectors perpendicular to `w`, then `w` itself
+fn orthonormal_basis(w: vec3<f32>) -> mat3x3<f32> {
+    // Any vector not parallel to w completes the basis
+    var helper = vec3<f32>(1.0, 0.0, 0.0);
+    if (abs(w.x) > 0.9) {
+        helper = vec3<f32>(0.0, 1.0, 0.0);
+    }
+    let u = normalize(cross(helper, w));
+    let v = cross(w, u);
+
+    return mat3x3<f32>(u, v, w);
+}
+
+// Tints `color` with the projection texture where `position` lands on
+// screen, positions outside the view keep `color`
+fn projected_color(position: vec3<f32>, color: vec4<f32>) -> vec4<f32> {
+    let clip = uniforms.projection_view_proj * vec4(position, 1.0);
+    if (clip.w <= 0.0) {
+        return color;
+    }
+
+    let ndc = clip.xy / clip.w;
+    if (any(abs(ndc) > vec2(1.0))) {
+        return color;
+    }
+
+    let uv = vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    return color * textureSampleLevel(projection_texture, projection_sampler, uv, 0.0);
+}
+
 @compute @workgroup_size(256)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index = global_id.x;
@@ -74,34 +220,146 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         return;
     }
 
+    if (uniforms.debug != 0u) {
+        atomicAdd(&debug_counters[0], 1u);
+    }
+
     let write_index = atomicAdd(&indirect_buffer[1], 1u);
     if (write_index >= arrayLength(&particles)) {
         return;
     }
 
-    var seed = hash(hash(write_index) ^ (bitcast<u32>(uniforms.elapsed_time)));
+    var seed = hash(hash(write_index ^ uniforms.seed) ^ (bitcast<u32>(uniforms.elapsed_time)));
+
+    // Halton index 0 maps every dimension to 0, start at 1
+    sample_index = uniforms.sample_base + index + 1u;
+    sample_dimension = 0u;
+
+    let thickness = uniforms.shell_thickness;
+    let extents = uniforms.extents.xyz;
+    let radius = max(extents + random_range(&seed, -thickness, thickness), vec3(0.0));
 
-    let scale = 8.0;
     var vector = vec3(0.0, 0.0, 0.0);
-    if (uniforms.shape == 0u) {
+    if (uniforms.shape == 0u || uniforms.shape == 6u) {
+        // Cones start at the apex and only differ in velocity
         vector = vec3(0.0, 0.0, 0.0);
+    } else if (uniforms.shape == 3u) {
+        let t = random_float(&seed);
+        let start = uniforms.line_start.xyz;
+        let end = uniforms.line_end.xyz;
+        vector = mix(start, end, t);
+
+        // Offset perpendicular to the segment to give the line some thickness
+        var offset = random_on_sphere(&seed);
+        let length_squared = dot(end - start, end - start);
+        if (length_squared > 0.0) {
+            offset -= (end - start) * dot(offset, end - start) / length_squared;
+        }
+        vector += offset * random_float(&seed) * thickness;
+    } else if (uniforms.shape == 4u || uniforms.shape == 5u) {
+        let radius = uniforms.curve.x;
+        let pitch = uniforms.curve.y;
+        let turns = uniforms.curve.z;
+
+        let t = random_float(&seed);
+        let angle = t * turns * 2.0 * 3.14159265359 + uniforms.curve.w;
+
+        if (uniforms.shape == 4u) {
+            let height = (t - 0.5) * turns * pitch;
+            vector = vec3(radius * cos(angle), height, radius * sin(angle));
+        } else {
+            vector = vec3(cos(angle), 0.0, sin(angle)) * radius * t;
+        }
+
+        vector += random_on_sphere(&seed) * random_float(&seed) * thickness;
+    } else if (uniforms.shape == 7u) {
+        let radii = uniforms.disk_radii;
+        vector = random_on_disk(&seed, radii.x, radii.y, uniforms.disk_normal.xyz);
+        vector += uniforms.disk_normal.xyz * random_range(&seed, -thickness, thickness);
+    } else if (uniforms.distribution == 1u) {
+        // Gaussian cloud around the center, boxed in for the cube
+        vector = random_gaussian3(&seed) * uniforms.sigma;
+        if (uniforms.shape == 2u) {
+            vector = clamp(vector, -radius, radius);
+        }
     } else if (uniforms.shape == 1u) {
-        vector = random_on_sphere(&seed) * scale;
+        vector = random_on_sphere(&seed) * radius;
     } else if (uniforms.shape == 2u) {
-        vector = random_on_cube(&seed) * scale;
+        vector = random_on_cube(&seed) * radius;
     }
 
+    vector = rotate(uniforms.orientation, vector);
+
+    let scale = max((extents.x + extents.y + extents.z) / 3.0, 1e-3);
     let gravitational_constant = 10.0;
     let orbital_speed = sqrt(gravitational_constant / scale);
 
-    let up = vec3<f32>(0.0, 1.0, 0.0);
-    let tangent = normalize(cross(vector, up));
+    let up = rotate(uniforms.orientation, vec3<f32>(0.0, 1.0, 0.0));
+
+    // Lines start at rest, cones shoot out of the apex and every other shape
+    // orbits the center
+    var velocity = vec4(0.0, 0.0, 0.0, 0.0);
+    let axis = cross(vector, up);
+    if (uniforms.shape == 6u) {
+        let direction = random_in_cone(&seed, uniforms.cone.xyz, uniforms.cone.w);
+        velocity = vec4(rotate(uniforms.orientation, direction), 0.0);
+    } else if (uniforms.shape != 3u && length(axis) > 0.0) {
+        let tangent = normalize(axis);
+        velocity = vec4(tangent * orbital_speed, 0.0);
+    }
+
+    // An explicit speed sends particles away from the center instead, cones
+    // keeping their direction
+    if (uniforms.override_speed != 0u) {
+        var direction = vector;
+        if (uniforms.shape == 6u) {
+            direction = velocity.xyz;
+        }
+        if (length(direction) > 0.0) {
+            direction = normalize(direction);
+        } else {
+            direction = random_on_sphere(&seed);
+        }
+
+        let variance = uniforms.speed_variance;
+        let speed = max(uniforms.initial_speed + random_range(&seed, -variance, variance), 0.0);
+        velocity = vec4(direction * speed, 0.0);
+    }
 
-    let velocity = vec4(tangent * orbital_speed, 0.0);
+    // Spread this emission evenly along the path the emitter moved since the last one
+    var origin = uniforms.position;
+    if (uniforms.interpolate != 0u) {
+        let t = (f32(index) + 0.5) / f32(uniforms.count);
+        origin = mix(uniforms.previous_position, uniforms.position, t);
+    }
+
+    var lifetime = uniforms.lifetime;
+    if (uniforms.lifetime_variance > 0.0 && lifetime < 3.4e38) {
+        lifetime = max(lifetime + random_range(&seed, -1.0, 1.0) * uniforms.lifetime_variance, 0.0);
+    }
+
+    // Start somewhere in [0, lifetime * stagger) so a burst doesn't age in lockstep
+    var age = 0.0;
+    if (uniforms.age_stagger > 0.0 && lifetime < 3.4e38) {
+        age = random_float(&seed) * lifetime * uniforms.age_stagger;
+    }
+
+    let position = origin + vec4(vector, 0.0);
+
+    var color = uniforms.tint;
+    if (uniforms.project_color != 0u) {
+        color = projected_color(position.xyz, color);
+    }
 
-    particles[write_index].position = uniforms.position + vec4(vector, 0.0);
-    particles[write_index].velocity = velocity;
-    particles[write_index].mass = 1.0;
-    particles[write_index].lifetime = uniforms.lifetime;
-    particles[write_index].age = 0.0;
+    particles[write_index] = pack_particle(Particle(
+        position,
+        velocity,
+        1.0,
+        lifetime,
+        age,
+        atomicAdd(&indirect_buffer[4], 1u),
+        color,
+        0u,
+        uniforms.temperature,
+    ));
 }
\ No newline at end of file
@@ -0,0 +1,23 @@
+struct Particle {
+    position: vec4<f32>,
+    velocity: vec4<f32>,
+    mass: f32,
+    lifetime: f32,
+    age: f32,
+    id: u32,
+    color: vec4<f32>,
+    generation: u32,
+    // Kelvin, drives the blackbody render mode
+    temperature: f32,
+}
+
+// Particles are stored as they are at full precision
+alias PackedParticle = Particle;
+
+fn unpack_particle(packed: PackedParticle) -> Particle {
+    return packed;
+}
+
+fn pack_particle(particle: Particle) -> PackedParticle {
+    return particle;
+}
@@ -0,0 +1,61 @@
+struct Particle {
+    position: vec4<f32>,
+    velocity: vec4<f32>,
+    mass: f32,
+    lifetime: f32,
+    age: f32,
+    id: u32,
+    color: vec4<f32>,
+    generation: u32,
+    // Kelvin, drives the blackbody render mode
+    temperature: f32,
+}
+
+// Position, velocity and color stored as four halves each
+struct PackedParticle {
+    position: vec2<u32>,
+    velocity: vec2<u32>,
+    mass: f32,
+    lifetime: f32,
+    age: f32,
+    id: u32,
+    color: vec2<u32>,
+    generation: u32,
+    temperature: f32,
+}
+
+fn unpack_half4(packed: vec2<u32>) -> vec4<f32> {
+    return vec4<f32>(unpack2x16float(packed.x), unpack2x16float(packed.y));
+}
+
+fn pack_half4(value: vec4<f32>) -> vec2<u32> {
+    return vec2<u32>(pack2x16float(value.xy), pack2x16float(value.zw));
+}
+
+fn unpack_particle(packed: PackedParticle) -> Particle {
+    return Particle(
+        unpack_half4(packed.position),
+        unpack_half4(packed.velocity),
+        packed.mass,
+        packed.lifetime,
+        packed.age,
+        packed.id,
+        unpack_half4(packed.color),
+        packed.generation,
+        packed.temperature,
+    );
+}
+
+fn pack_particle(particle: Particle) -> PackedParticle {
+    return PackedParticle(
+        pack_half4(particle.position),
+        pack_half4(particle.velocity),
+        particle.mass,
+        particle.lifetime,
+        particle.age,
+        particle.id,
+        pack_half4(particle.color),
+        particle.generation,
+        particle.temperature,
+    );
+}
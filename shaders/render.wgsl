@@ -2,37 +2,260 @@ struct RenderUniforms {
     view_proj: mat4x4<f32>,
     color_start: vec4<f32>,
     color_end: vec4<f32>,
+    mode: u32,
+    ghost_fade: f32,
+    alpha_start: f32,
+    alpha_end: f32,
+    viewport_size: vec2<f32>,
+    point_size: f32,
+    line_width: f32,
+    render_stride: u32,
+    size_start: f32,
+    size_end: f32,
+    // Non-zero to draw through sorted_indices
+    sorted: u32,
 }
 
-struct Particle {
-    position: vec4<f32>,
-    velocity: vec4<f32>,
-    mass: f32,
-    lifetime: f32,
-    age: f32,
-}
+// Particle and PackedParticle come from the particle.wgsl prelude
 
 @group(0) @binding(0) var<uniform> uniforms: RenderUniforms;
-@group(0) @binding(1) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read> particles: array<PackedParticle>;
+@group(0) @binding(2) var<storage, read> previous_particles: array<PackedParticle>;
+// Particle indices ordered back to front, filled by sort.wgsl
+@group(0) @binding(3) var<storage, read> sorted_indices: array<u32>;
+@group(0) @binding(4) var sprite_texture: texture_2d<f32>;
+@group(0) @binding(5) var sprite_sampler: sampler;
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec4<f32>,
+    // Position inside a round point's or sprite's quad, from -1 to 1 on each axis
+    @location(1) uv: vec2<f32>,
+    // Diameter in pixels of a point drawn as a quad
+    @location(2) size: f32,
 }
 
-@vertex
-fn vs_main(@builtin(instance_index) instance_index: u32) -> VertexOutput {
-    let particle = particles[instance_index];
+// Places the vertex outside the clip volume so nothing is drawn
+fn culled() -> VertexOutput {
+    var out: VertexOutput;
+
+    out.clip_position = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+    out.color = vec4<f32>(0.0);
+    out.uv = vec2<f32>(0.0);
+    out.size = 0.0;
+
+    return out;
+}
+
+// Tanner Helland's fit of the color of a black body, fading to black below 1500 K
+fn blackbody(kelvin: f32) -> vec3<f32> {
+    let t = clamp(kelvin, 1000.0, 40000.0) / 100.0;
+
+    var color: vec3<f32>;
+    if (t <= 66.0) {
+        color.r = 255.0;
+        color.g = 99.4708025861 * log(t) - 161.1195681661;
+    } else {
+        color.r = 329.698727446 * pow(t - 60.0, -0.1332047592);
+        color.g = 288.1221695283 * pow(t - 60.0, -0.0755148492);
+    }
+
+    if (t >= 66.0) {
+        color.b = 255.0;
+    } else if (t <= 19.0) {
+        color.b = 0.0;
+    } else {
+        color.b = 138.5177312231 * log(t - 10.0) - 305.0447927307;
+    }
+
+    let glow = clamp((kelvin - 500.0) / 1000.0, 0.0, 1.0);
+    return clamp(color / 255.0, vec3(0.0), vec3(1.0)) * glow;
+}
+
+// Color math mirrored on the CPU by RenderUniforms::particle_color
+fn particle_vertex(instance_index: u32, ghost: bool) -> VertexOutput {
+    if (instance_index % uniforms.render_stride != 0u) {
+        return culled();
+    }
+
+    var index = instance_index;
+    if (uniforms.sorted != 0u) {
+        index = sorted_indices[instance_index];
+    }
+
+    var particle = unpack_particle(particles[index]);
+    var fade = 1.0;
+
+    // Dead or never emitted, only reachable when drawing without compaction
+    if (particle.age >= particle.lifetime) {
+        return culled();
+    }
 
     var out: VertexOutput;
 
+    if (ghost) {
+        let previous = unpack_particle(previous_particles[index]);
+
+        // Slots filled by this frame's emission hold stale data in the previous buffer
+        if (previous.id != particle.id || previous.age >= particle.age) {
+            return culled();
+        }
+
+        particle = previous;
+        fade = uniforms.ghost_fade;
+    }
+
     out.clip_position = uniforms.view_proj * particle.position;
-    out.color = uniforms.color_start + (uniforms.color_end - uniforms.color_start) * (particle.age / particle.lifetime);
+    // Immortal particles keep the start of the gradient
+    var t = 0.0;
+    if (particle.lifetime < 3.4e38) {
+        t = particle.age / particle.lifetime;
+    }
+    let rgb = mix(uniforms.color_start.rgb, uniforms.color_end.rgb, t);
+    let alpha = mix(uniforms.alpha_start, uniforms.alpha_end, t);
+    out.color = vec4<f32>(rgb, alpha);
+    out.color *= particle.color;
+
+    if (uniforms.mode == 1u) {
+        let speed = length(particle.velocity.xyz);
+        var direction = vec3<f32>(0.0, 0.0, 0.0);
+        if (speed > 0.0) {
+            direction = particle.velocity.xyz / speed;
+        }
+
+        out.color = vec4<f32>(direction * 0.5 + 0.5, out.color.a);
+    } else if (uniforms.mode == 2u) {
+        out.color = vec4<f32>(blackbody(particle.temperature), out.color.a);
+    }
+
+    out.color.a *= fade;
+    out.uv = vec2<f32>(0.0);
+    out.size = uniforms.point_size * mix(uniforms.size_start, uniforms.size_end, t);
+
+    return out;
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    // The second vertex of an instance is its ghost from the previous frame
+    return particle_vertex(instance_index, vertex_index == 1u);
+}
+
+// Expands each particle into a screen-aligned quad of two triangles, the
+// second quad of an instance being its ghost
+@vertex
+fn vs_round(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>( 1.0, -1.0),
+        vec2<f32>( 1.0,  1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>( 1.0,  1.0),
+        vec2<f32>(-1.0,  1.0),
+    );
+    let corner = corners[vertex_index % 6u];
+
+    var out = particle_vertex(instance_index, vertex_index >= 6u);
+
+    // Offset in NDC, scaled by w so the size stays constant on screen
+    let offset = corner * out.size / uniforms.viewport_size;
+    out.clip_position += vec4<f32>(offset * out.clip_position.w, 0.0, 0.0);
+    out.uv = corner;
+
+    return out;
+}
+
+// Expands each particle into a quad stretched from its previous position to
+// its current one, `line_width` pixels wide
+@vertex
+fn vs_streak(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    // x picks the end of the segment, y the side of the line
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0,  1.0),
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0,  1.0),
+        vec2<f32>(0.0,  1.0),
+    );
+    let corner = corners[vertex_index % 6u];
+
+    let current = particle_vertex(instance_index, false);
+    var previous = particle_vertex(instance_index, true);
+
+    // Culled vertices sit beyond the far plane
+    if (current.clip_position.z > current.clip_position.w) {
+        return current;
+    }
+    if (previous.clip_position.z > previous.clip_position.w) {
+        previous = current;
+    }
+    if (current.clip_position.w <= 0.0 || previous.clip_position.w <= 0.0) {
+        return culled();
+    }
+
+    let start = previous.clip_position.xy / previous.clip_position.w;
+    let end = current.clip_position.xy / current.clip_position.w;
+
+    // Direction in pixels so the width is the same whatever the aspect ratio
+    var direction = vec2<f32>(1.0, 0.0);
+    let delta = (end - start) * uniforms.viewport_size;
+    if (length(delta) > 0.0) {
+        direction = normalize(delta);
+    }
+    let normal = vec2<f32>(-direction.y, direction.x);
+
+    var out = previous;
+    if (corner.x > 0.0) {
+        out = current;
+    }
+
+    let offset = normal * corner.y * uniforms.line_width / uniforms.viewport_size;
+    out.clip_position += vec4<f32>(offset * out.clip_position.w, 0.0, 0.0);
+    out.uv = corner;
 
     return out;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return in.color;
-}
\ No newline at end of file
+    return vec4<f32>(in.color.rgb * in.color.a, in.color.a);
+}
+
+@fragment
+fn fs_round(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Fade the edge of the circle over about a pixel
+    let distance = length(in.uv);
+    let coverage = clamp((1.0 - distance) / max(fwidth(distance), 1e-5), 0.0, 1.0);
+    let alpha = in.color.a * coverage;
+
+    return vec4<f32>(in.color.rgb * alpha, alpha);
+}
+
+@fragment
+fn fs_sprite(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Quad y points up, texture v down
+    let texel = textureSample(sprite_texture, sprite_sampler, vec2<f32>(in.uv.x, -in.uv.y) * 0.5 + 0.5);
+    let alpha = in.color.a * texel.a;
+
+    return vec4<f32>(in.color.rgb * texel.rgb * alpha, alpha);
+}
+
+@fragment
+fn fs_streak(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Fade the sides of the line over about a pixel
+    let distance = abs(in.uv.y);
+    let coverage = clamp((1.0 - distance) / max(fwidth(distance), 1e-5), 0.0, 1.0);
+    let alpha = in.color.a * coverage;
+
+    return vec4<f32>(in.color.rgb * alpha, alpha);
+}
@@ -0,0 +1,67 @@
+struct SortUniforms {
+    // Row of view_proj giving a particle's depth, w for perspective and z for
+    // orthographic projections
+    depth_axis: vec4<f32>,
+}
+
+// One merge step of the bitonic network, bound at a dynamic offset
+struct SortStage {
+    // Size of the sequences being merged
+    block: u32,
+    // Distance between compared elements
+    stride: u32,
+}
+
+// Particle and PackedParticle come from the particle.wgsl prelude
+
+@group(0) @binding(0) var<uniform> uniforms: SortUniforms;
+@group(0) @binding(1) var<storage, read> particles: array<PackedParticle>;
+@group(0) @binding(2) var<storage, read> indirect_buffer: array<u32>;
+@group(0) @binding(3) var<storage, read_write> keys: array<f32>;
+@group(0) @binding(4) var<storage, read_write> indices: array<u32>;
+@group(0) @binding(5) var<uniform> stage: SortStage;
+
+// Keys every slot by depth. Dead slots and the padding up to the next power
+// of two get the lowest key so they sort after every live particle.
+@compute @workgroup_size(256)
+fn init(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= arrayLength(&keys)) {
+        return;
+    }
+
+    var key = -3.4e38;
+    let count = min(indirect_buffer[1], arrayLength(&particles));
+    if (index < count) {
+        let particle = unpack_particle(particles[index]);
+        if (particle.age < particle.lifetime) {
+            key = dot(uniforms.depth_axis, particle.position);
+        }
+    }
+
+    keys[index] = key;
+    indices[index] = index;
+}
+
+// Compare and swap of one bitonic stage, ordering the farthest particles first
+@compute @workgroup_size(256)
+fn sort_step(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    let partner = index ^ stage.stride;
+    if (partner <= index || partner >= arrayLength(&keys)) {
+        return;
+    }
+
+    let a = keys[index];
+    let b = keys[partner];
+    let descending = (index & stage.block) == 0u;
+
+    if ((descending && a < b) || (!descending && a > b)) {
+        keys[index] = b;
+        keys[partner] = a;
+
+        let swapped = indices[index];
+        indices[index] = indices[partner];
+        indices[partner] = swapped;
+    }
+}
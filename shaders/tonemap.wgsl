@@ -0,0 +1,45 @@
+struct TonemapUniforms {
+    mode: u32,
+    exposure: f32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: TonemapUniforms;
+@group(0) @binding(1) var hdr_texture: texture_2d<f32>;
+
+// Single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return (color * (a * color + b)) / (color * (c * color + d) + e);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let hdr = textureLoad(hdr_texture, vec2<i32>(position.xy), 0);
+    var color = hdr.rgb * uniforms.exposure;
+
+    if (uniforms.mode == 1u) {
+        color = reinhard(color);
+    } else if (uniforms.mode == 2u) {
+        color = aces(color);
+    }
+
+    let alpha = clamp(hdr.a, 0.0, 1.0);
+
+    // Keep the output premultiplied for transparent surfaces
+    return vec4<f32>(min(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(alpha)), alpha);
+}
@@ -1,57 +1,317 @@
 struct UpdateUniforms {
-    gravity_center: vec4<f32>,
     elapsed_time: f32,
     delta_time: f32,
+    repulsion_radius: f32,
+    repulsion_strength: f32,
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+    // 0: unbounded, 1: wrap around
+    bounds_mode: u32,
+    // Non-zero to count active invocations in debug_counters
+    debug: u32,
+    // 0: no velocity field, 1: replace the velocity, 2: accelerate by it
+    field_mode: u32,
+    // Children spawned by a first generation particle when it dies
+    child_count: u32,
+    field_min: vec4<f32>,
+    field_max: vec4<f32>,
+    child_speed: f32,
+    // Kelvin lost per second
+    cooling_rate: f32,
+    // Particles closer than this to any attractor die, 0 disables it
+    absorb_radius: f32,
+    attractor_count: u32,
+    // Velocity decays by exp(-drag) per second
+    drag: f32,
+    // Curl noise acceleration, 0 disables it
+    turbulence_strength: f32,
+    // Size of the curl noise swirls in world units
+    turbulence_scale: f32,
+    wind: vec4<f32>,
 }
 
-struct Particle {
+struct Attractor {
     position: vec4<f32>,
-    velocity: vec4<f32>,
     mass: f32,
-    lifetime: f32,
-    age: f32,
 }
 
+// Particle and PackedParticle come from the particle.wgsl prelude
+
 @group(0) @binding(0) var<uniform> uniforms: UpdateUniforms;
-@group(0) @binding(1) var<storage, read> particles_in: array<Particle>;
-@group(0) @binding(2) var<storage, read_write> particles_out: array<Particle>;
+@group(0) @binding(1) var<storage, read> particles_in: array<PackedParticle>;
+@group(0) @binding(2) var<storage, read_write> particles_out: array<PackedParticle>;
+@group(0) @binding(3) var<storage, read> cell_heads: array<u32>;
+@group(0) @binding(4) var<storage, read> next: array<u32>;
+// Active emit invocations, then active update invocations
+@group(0) @binding(5) var<storage, read_write> debug_counters: array<atomic<u32>>;
+@group(0) @binding(6) var velocity_field: texture_3d<f32>;
+// Indirect draw args, then the id of the next particle
+@group(0) @binding(7) var<storage, read_write> indirect_buffer: array<atomic<u32>>;
+// Particle count before compaction, then after it
+@group(0) @binding(8) var<storage, read> counts: array<u32>;
+@group(0) @binding(9) var<storage, read> attractors: array<Attractor>;
+
+// Caps the work per particle in dense regions
+const MAX_NEIGHBORS: u32 = 64u;
+
+fn hash_cell(cell: vec3<i32>) -> u32 {
+    let c = bitcast<vec3<u32>>(cell);
+    let h = (c.x * 73856093u) ^ (c.y * 19349663u) ^ (c.z * 83492791u);
+    return h % arrayLength(&cell_heads);
+}
+
+// Sums the push away from every particle closer than the repulsion radius,
+// looking only at the 27 grid cells around this particle
+fn repulsion(index: u32, position: vec3<f32>) -> vec3<f32> {
+    let radius = uniforms.repulsion_radius;
+    let cell = vec3<i32>(floor(position / radius));
+
+    var force = vec3<f32>(0.0);
+    var visited = 0u;
+
+    for (var z = -1; z <= 1; z++) {
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                var entry = cell_heads[hash_cell(cell + vec3<i32>(x, y, z))];
+
+                while (entry != 0u && visited < MAX_NEIGHBORS) {
+                    let other = entry - 1u;
+                    entry = next[other];
+
+                    if (other == index) {
+                        continue;
+                    }
+
+                    let offset = position - unpack_particle(particles_in[other]).position.xyz;
+                    let distance = length(offset);
+                    if (distance > 0.0 && distance < radius) {
+                        force += offset / distance * (1.0 - distance / radius);
+                        visited++;
+                    }
+                }
+            }
+        }
+    }
+
+    return force * uniforms.repulsion_strength;
+}
+
+fn hash(x: u32) -> u32 {
+    var s = x;
+    s = (s ^ 61u) ^ (s >> 16u);
+    s = s + (s << 3u);
+    s = s ^ (s >> 4u);
+    s = s * 0x27d4eb2du;
+    s = s ^ (s >> 15u);
+    return s;
+}
+
+fn random_float(state: ptr<function, u32>) -> f32 {
+    *state = hash(*state);
+    return f32(*state) / f32(0xFFFFFFFFu);
+}
+
+fn random_on_sphere(state: ptr<function, u32>) -> vec3<f32> {
+    let theta = random_float(state) * 2.0 * 3.14159265359;
+    let phi = acos(2.0 * random_float(state) - 1.0);
+
+    return vec3<f32>(sin(phi) * cos(theta), sin(phi) * sin(theta), cos(phi));
+}
+
+// Appends the children of a particle that just died, the same way emit
+// appends new particles
+fn spawn_children(index: u32, parent: Particle) {
+    var seed = hash(hash(index) ^ bitcast<u32>(uniforms.elapsed_time));
+
+    for (var i = 0u; i < uniforms.child_count; i++) {
+        let write_index = atomicAdd(&indirect_buffer[1], 1u);
+        if (write_index >= arrayLength(&particles_out)) {
+            return;
+        }
+
+        var child = parent;
+        child.velocity = vec4(random_on_sphere(&seed) * uniforms.child_speed, 0.0);
+        child.age = 0.0;
+        child.generation = parent.generation + 1u;
+        // Emit hands out ids from the same counter
+        child.id = atomicAdd(&indirect_buffer[4], 1u);
+        particles_out[write_index] = pack_particle(child);
+    }
+}
+
+fn corner_gradient(cell: vec3<i32>) -> vec3<f32> {
+    let c = bitcast<vec3<u32>>(cell);
+    let h = hash(c.x ^ hash(c.y ^ hash(c.z)));
+    let bits = vec3<u32>(h, h >> 10u, h >> 20u) & vec3(0x3ffu);
+
+    return vec3<f32>(bits) / 1023.0 * 2.0 - 1.0;
+}
+
+// Gradient noise with quintic fade, the value in x and its analytic
+// derivative in yzw
+fn gradient_noise(p: vec3<f32>) -> vec4<f32> {
+    let cell = floor(p);
+    let f = p - cell;
+    let u = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    let du = 30.0 * f * f * (f * (f - 2.0) + 1.0);
+
+    var result = vec4<f32>(0.0);
+    for (var corner = 0; corner < 8; corner++) {
+        let offset = vec3<i32>(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+        let gradient = corner_gradient(vec3<i32>(cell) + offset);
+        let value = dot(gradient, f - vec3<f32>(offset));
+
+        let weights = select(1.0 - u, u, offset == vec3(1));
+        let slopes = select(-du, du, offset == vec3(1));
+        let weight = weights.x * weights.y * weights.z;
+        let weight_gradient = vec3<f32>(
+            slopes.x * weights.y * weights.z,
+            weights.x * slopes.y * weights.z,
+            weights.x * weights.y * slopes.z,
+        );
+
+        result += vec4(weight * value, weight * gradient + weight_gradient * value);
+    }
+
+    return result;
+}
+
+// Curl of a vector potential made of three decorrelated noises, which has no
+// divergence so particles swirl without bunching up
+fn curl_noise(p: vec3<f32>) -> vec3<f32> {
+    let x = gradient_noise(p);
+    let y = gradient_noise(p + vec3(31.416, -47.853, 12.793));
+    let z = gradient_noise(p + vec3(-233.145, -113.408, 185.31));
+
+    return vec3(z.z - y.w, x.w - z.y, y.y - x.z);
+}
+
+// Velocity scale over one step, mirrors drag_factor in particle_system.rs
+fn drag_factor(drag: f32, dt: f32) -> f32 {
+    return exp(-drag * dt);
+}
+
+// Trilinear interpolation of the velocity field, `uvw` in [0, 1] across its bounds
+fn sample_velocity_field(uvw: vec3<f32>) -> vec3<f32> {
+    let dims = vec3<i32>(textureDimensions(velocity_field));
+    let texel = clamp(uvw * vec3<f32>(dims) - 0.5, vec3(0.0), vec3<f32>(dims - 1));
+    let base = vec3<i32>(floor(texel));
+    let t = texel - vec3<f32>(base);
+
+    var result = vec3<f32>(0.0);
+    for (var corner = 0; corner < 8; corner++) {
+        let offset = vec3<i32>(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+        let weights = select(1.0 - t, t, offset == vec3(1));
+        let coords = min(base + offset, dims - 1);
+        result += textureLoad(velocity_field, coords, 0).xyz * weights.x * weights.y * weights.z;
+    }
+
+    return result;
+}
 
 @compute @workgroup_size(256)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index = global_id.x;
-    if (index >= arrayLength(&particles_in)) {
+    // Slots past the count are free, children may be appended there
+    if (index >= arrayLength(&particles_in) || index >= counts[1]) {
         return;
     }
 
+    if (uniforms.debug != 0u) {
+        atomicAdd(&debug_counters[1], 1u);
+    }
+
     let dt = uniforms.delta_time;
 
     // Read from input buffer
-    let particle = particles_in[index];
+    let particle = unpack_particle(particles_in[index]);
 
-    // Calculate direction and distance to gravity center
-    let to_center = uniforms.gravity_center - particle.position;
-    let distance = length(to_center);
+    var acceleration = vec4(uniforms.wind.xyz, 0.0);
+    for (var i = 0u; i < uniforms.attractor_count; i++) {
+        let attractor = attractors[i];
+        let to_center = attractor.position.xyz - particle.position.xyz;
+        let distance = length(to_center);
 
-    // Prevent division by zero and extreme forces
-    let min_distance = 0.1;
-    let safe_distance = max(distance, min_distance);
+        // Prevent division by zero and extreme forces
+        let min_distance = 0.1;
+        let safe_distance = max(distance, min_distance);
 
-    // Calculate gravitational force (F = G * m / r^2)
-    let direction = normalize(to_center);
-    let force_magnitude = 10.0 / (safe_distance * safe_distance);
-    let acceleration = direction * force_magnitude;
+        // Calculate gravitational force (F = G * m / r^2)
+        if (distance > 0.0) {
+            let force_magnitude = attractor.mass / (safe_distance * safe_distance);
+            acceleration += vec4(to_center / distance * force_magnitude, 0.0);
+        }
+    }
+
+    if (uniforms.turbulence_strength != 0.0) {
+        let turbulence = curl_noise(particle.position.xyz / uniforms.turbulence_scale);
+        acceleration += vec4(turbulence * uniforms.turbulence_strength, 0.0);
+    }
+
+    if (uniforms.repulsion_radius > 0.0 && particle.age < particle.lifetime) {
+        acceleration += vec4(repulsion(index, particle.position.xyz) / particle.mass, 0.0);
+    }
 
     // Update velocity and position
-    let velocity = particle.velocity + acceleration * dt;
-    let position = particle.position + velocity * dt;
+    var velocity = (particle.velocity + acceleration * dt) * drag_factor(uniforms.drag, dt);
+
+    if (uniforms.field_mode != 0u) {
+        let size = uniforms.field_max.xyz - uniforms.field_min.xyz;
+        let uvw = (particle.position.xyz - uniforms.field_min.xyz) / size;
+
+        if (all(uvw >= vec3(0.0)) && all(uvw <= vec3(1.0))) {
+            let field = sample_velocity_field(uvw);
+            if (uniforms.field_mode == 1u) {
+                velocity = vec4(field, 0.0);
+            } else {
+                velocity += vec4(field, 0.0) * dt;
+            }
+        }
+    }
+
+    var position = particle.position + velocity * dt;
+
+    // Leaving the box through one face re-enters it through the opposite one
+    if (uniforms.bounds_mode == 1u) {
+        let size = uniforms.bounds_max.xyz - uniforms.bounds_min.xyz;
+        let relative = position.xyz - uniforms.bounds_min.xyz;
+        let wrapped = uniforms.bounds_min.xyz + relative - size * floor(relative / size);
+        position = vec4(select(position.xyz, wrapped, size > vec3(0.0)), position.w);
+    }
     let mass = particle.mass;
     let lifetime = particle.lifetime;
-    let age = particle.age + dt;
+    var age = particle.age + dt;
+    let id = particle.id;
+    let color = particle.color;
+
+    // Absorbed particles are dead as of this frame, without leaving children
+    var absorbed = false;
+    for (var i = 0u; i < uniforms.attractor_count; i++) {
+        if (distance(position.xyz, attractors[i].position.xyz) < uniforms.absorb_radius) {
+            absorbed = true;
+        }
+    }
+    if (absorbed) {
+        age = max(age, lifetime);
+    }
+
+    if (uniforms.child_count > 0u && particle.generation == 0u && !absorbed
+        && particle.age < lifetime && age >= lifetime) {
+        var parent = particle;
+        parent.position = position;
+        spawn_children(index, parent);
+    }
 
     // Write to output buffer
-    particles_out[index].position = position;
-    particles_out[index].velocity = velocity;
-    particles_out[index].mass = mass;
-    particles_out[index].lifetime = lifetime;
-    particles_out[index].age = age;
+    particles_out[index] = pack_particle(Particle(
+        position,
+        velocity,
+        mass,
+        lifetime,
+        age,
+        id,
+        color,
+        particle.generation,
+        max(particle.temperature - uniforms.cooling_rate * dt, 0.0),
+    ));
 }
@@ -1,20 +1,23 @@
 use {
     crate::{
-        camera::Camera,
+        camera::{Camera, CameraState, ProjectionMode},
+        camera_controller::CameraController,
+        config::Config,
         input_handler::InputHandler,
-        particle_system::{
-            ParticleEmissionMode, ParticleEmissionShape, ParticleSystem, ParticleSystemInfo,
-            RenderUniforms, UpdateUniforms,
-        },
-        renderer::Renderer,
+        particle_system::{ParticleSystem, RenderMode, RestartMode},
+        renderer::{Renderer, RendererError, Tonemap},
         timer::Timer,
     },
     core::f32,
-    std::sync::Arc,
+    std::{
+        path::PathBuf,
+        sync::Arc,
+        time::{SystemTime, UNIX_EPOCH},
+    },
     winit::{
         application::ApplicationHandler,
         dpi::{PhysicalPosition, PhysicalSize},
-        event::{DeviceEvent, DeviceId, ElementState, WindowEvent},
+        event::{DeviceEvent, DeviceId, ElementState, MouseScrollDelta, WindowEvent},
         event_loop::ActiveEventLoop,
         keyboard::{KeyCode, PhysicalKey},
         window::{Fullscreen, Window, WindowId},
@@ -27,15 +30,129 @@ struct Parameters {
     move_speed: f32,
 }
 
+/// Where the gravity center is placed each frame
+#[derive(Clone, Copy, Default, PartialEq)]
+enum AttractorSource {
+    /// A fixed distance in front of the camera
+    #[default]
+    CameraForward,
+    /// Under the mouse cursor, at the same distance from the camera
+    Cursor,
+    /// The world origin
+    Fixed,
+}
+
 #[derive(Default)]
 pub struct App {
     window: Option<Arc<Window>>,
     renderer: Option<Renderer>,
     camera: Camera,
+    config: Config,
+    camera_controller: CameraController,
     timer: Timer,
     particle_system: Option<ParticleSystem>,
     input_handler: InputHandler,
     parameters: Parameters,
+    camera_locked: bool,
+    show_hud: bool,
+    show_grid: bool,
+    restart_mode: RestartMode,
+    attractor_source: AttractorSource,
+    cursor_position: PhysicalPosition<f64>,
+}
+
+impl App {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Replaces live camera input with playback of `path` until it ends
+    pub fn set_flythrough(&mut self, path: &[CameraState]) {
+        self.camera_controller.set_target_path(path);
+    }
+
+    fn attractor_position(&self, width: u32, height: u32) -> glam::Vec3 {
+        let distance = 20.0;
+
+        match self.attractor_source {
+            AttractorSource::CameraForward => {
+                self.camera.position() + self.camera.forward() * distance
+            }
+            AttractorSource::Cursor => {
+                let (origin, direction) = self.camera.screen_to_world_ray(
+                    self.cursor_position.x as f32,
+                    self.cursor_position.y as f32,
+                    width,
+                    height,
+                );
+
+                // Intersect with the plane facing the camera at `distance`
+                let forward = self.camera.forward();
+                let plane_point = self.camera.position() + forward * distance;
+                let denominator = direction.dot(forward);
+                if denominator.abs() < 1e-6 {
+                    return plane_point;
+                }
+
+                origin + direction * (plane_point - origin).dot(forward) / denominator
+            }
+            AttractorSource::Fixed => glam::Vec3::ZERO,
+        }
+    }
+
+    fn create_graphics(
+        window: Arc<Window>,
+        config: &Config,
+    ) -> Result<(Renderer, ParticleSystem), RendererError> {
+        let info = config.particle_system;
+        let storage_buffer_size = ParticleSystem::required_buffer_size(&info);
+
+        let renderer = pollster::block_on(
+            Renderer::builder()
+                .storage_buffer_size(storage_buffer_size)
+                .sample_count(4)
+                .build(window),
+        )?;
+
+        let mut particle_system =
+            ParticleSystem::new(renderer.device(), renderer.target_format(), info);
+        particle_system.set_sample_count(renderer.device(), renderer.sample_count());
+        particle_system.set_burst_frames(10);
+        particle_system.set_temperature(6500.0, 100.0);
+        particle_system.set_absorb_radius(config.absorb_radius);
+        particle_system.set_particle_repulsion(config.repulsion.0, config.repulsion.1);
+
+        Ok((renderer, particle_system))
+    }
+
+    /// Rebuilds the renderer and particle system after the GPU device was
+    /// lost. Returns false if recovery failed.
+    fn recover_lost_device(&mut self) -> bool {
+        let Some(window) = self.window.clone() else {
+            return false;
+        };
+
+        eprintln!("Recreating renderer after device loss");
+
+        // Release everything tied to the old device first
+        self.particle_system = None;
+        self.renderer = None;
+
+        match Self::create_graphics(window, &self.config) {
+            Ok((renderer, particle_system)) => {
+                self.renderer = Some(renderer);
+                self.particle_system = Some(particle_system);
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to recover from device loss: {e}");
+                false
+            }
+        }
+    }
 }
 
 impl ApplicationHandler for App {
@@ -60,8 +177,9 @@ impl ApplicationHandler for App {
             }
         };
 
-        let renderer = match pollster::block_on(Renderer::new(window.clone())) {
-            Ok(r) => r,
+        let (renderer, particle_system) = match Self::create_graphics(window.clone(), &self.config)
+        {
+            Ok(graphics) => graphics,
             Err(e) => {
                 eprintln!("Failed to create renderer: {e}");
                 event_loop.exit();
@@ -69,28 +187,7 @@ impl ApplicationHandler for App {
             }
         };
 
-        self.camera = Camera::new(
-            glam::vec3(0.0, 0.0, 20.0),
-            glam::vec3(0.0, 0.0, 0.0),
-            glam::vec3(0.0, 1.0, 0.0),
-            width as f32 / height as f32,
-            (120.0f32).to_radians(),
-            0.1,
-            1000.0,
-        );
-
-        let surface_format = renderer.surface_format();
-
-        let particle_system = ParticleSystem::new(
-            renderer.device(),
-            surface_format,
-            ParticleSystemInfo {
-                position: glam::Vec3::ZERO,
-                shape: ParticleEmissionShape::Sphere,
-                mode: ParticleEmissionMode::Burst(100000),
-                lifetime: f32::INFINITY,
-            },
-        );
+        self.camera = Camera::from_config(&self.config.camera, width as f32 / height as f32);
 
         let parameters = Parameters {
             sensitivity: 1.0,
@@ -101,26 +198,30 @@ impl ApplicationHandler for App {
         self.window = Some(window);
         self.renderer = Some(renderer);
 
+        self.camera_controller.set_max_speed(parameters.move_speed);
         self.parameters = parameters;
+        self.show_hud = true;
         self.input_handler = InputHandler::new();
         self.timer = Timer::new();
     }
 
     fn device_event(&mut self, _: &ActiveEventLoop, _: DeviceId, event: DeviceEvent) {
-        if let DeviceEvent::MouseMotion { delta: (dx, dy) } = event {
-            if let Some(window) = &self.window {
-                let size = window.inner_size();
-
-                let x = (dx as f32 / size.width as f32) * self.parameters.sensitivity;
-                let y = (dy as f32 / size.height as f32) * self.parameters.sensitivity;
-
-                self.camera.rotate(x, y);
-
-                // Reset cursor to center
-                let center = PhysicalPosition::new(size.width / 2, size.height / 2);
-                if let Err(e) = window.set_cursor_position(center) {
-                    eprintln!("Failed to set cursor position: {e:?}");
-                }
+        if let DeviceEvent::MouseMotion { delta: (dx, dy) } = event
+            && let Some(window) = &self.window
+            && !self.camera_controller.is_active()
+            && !self.camera_locked
+        {
+            let size = window.inner_size();
+
+            let x = (dx as f32 / size.width as f32) * self.parameters.sensitivity;
+            let y = (dy as f32 / size.height as f32) * self.parameters.sensitivity;
+
+            self.camera.rotate(x, y);
+
+            // Reset cursor to center
+            let center = PhysicalPosition::new(size.width / 2, size.height / 2);
+            if let Err(e) = window.set_cursor_position(center) {
+                eprintln!("Failed to set cursor position: {e:?}");
             }
         }
     }
@@ -131,7 +232,7 @@ impl ApplicationHandler for App {
         window_id: WindowId,
         event: WindowEvent,
     ) {
-        let window = match self.window.as_mut() {
+        let window = match self.window.clone() {
             Some(window) => window,
             None => return,
         };
@@ -144,6 +245,18 @@ impl ApplicationHandler for App {
             WindowEvent::CloseRequested => {
                 event_loop.exit();
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = position;
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                };
+
+                // Each line up narrows the view by 5%
+                self.camera.set_fov(self.camera.fov() * 0.95f32.powf(lines));
+            }
             WindowEvent::Resized(physical_size) => {
                 let width = physical_size.width;
                 let height = physical_size.height;
@@ -202,66 +315,190 @@ impl ApplicationHandler for App {
                                 particle_system.pause();
                             }
                         }
+                        KeyCode::KeyU => {
+                            if let Some(particle_system) = &mut self.particle_system {
+                                particle_system.set_simulation_enabled(
+                                    !particle_system.is_simulation_enabled(),
+                                );
+                            }
+                        }
+                        KeyCode::KeyI => {
+                            if let Some(particle_system) = &mut self.particle_system {
+                                particle_system
+                                    .set_rendering_enabled(!particle_system.is_rendering_enabled());
+                            }
+                        }
+                        KeyCode::KeyC => {
+                            self.attractor_source = match self.attractor_source {
+                                AttractorSource::CameraForward => AttractorSource::Cursor,
+                                AttractorSource::Cursor => AttractorSource::Fixed,
+                                AttractorSource::Fixed => AttractorSource::CameraForward,
+                            };
+                        }
+                        KeyCode::KeyL => {
+                            // Freeze the camera and release the cursor
+                            self.camera_locked = !self.camera_locked;
+                            window.set_cursor_visible(self.camera_locked);
+                        }
+                        KeyCode::KeyO => {
+                            let mode = match self.camera.projection_mode() {
+                                ProjectionMode::Perspective => {
+                                    ProjectionMode::Orthographic { height: 40.0 }
+                                }
+                                ProjectionMode::Orthographic { .. } => ProjectionMode::Perspective,
+                            };
+                            self.camera.set_projection_mode(mode);
+                        }
+                        KeyCode::F2 => {
+                            if let Some(renderer) = &self.renderer {
+                                let path = screenshot_path();
+                                match renderer.capture_frame(&path) {
+                                    Ok(()) => println!("Saved screenshot to {}", path.display()),
+                                    Err(e) => eprintln!("Failed to save screenshot: {e}"),
+                                }
+                            }
+                        }
+                        KeyCode::KeyH => {
+                            self.show_hud = !self.show_hud;
+                        }
+                        KeyCode::KeyB => {
+                            self.show_grid = !self.show_grid;
+                        }
+                        KeyCode::KeyV => {
+                            if let Some(particle_system) = &mut self.particle_system {
+                                let mode = match particle_system.render_mode() {
+                                    RenderMode::Gradient => RenderMode::VelocityDebug,
+                                    RenderMode::VelocityDebug => RenderMode::Blackbody,
+                                    RenderMode::Blackbody => RenderMode::Gradient,
+                                };
+                                particle_system.set_render_mode(mode);
+                            }
+                        }
+                        KeyCode::KeyM => {
+                            if let Some(renderer) = &mut self.renderer {
+                                let tonemap = match renderer.tonemap() {
+                                    Tonemap::None => Tonemap::Reinhard,
+                                    Tonemap::Reinhard => Tonemap::Aces,
+                                    Tonemap::Aces => Tonemap::None,
+                                };
+                                renderer.set_tonemap(tonemap);
+                            }
+                        }
+                        KeyCode::KeyG => {
+                            if let Some(particle_system) = &mut self.particle_system {
+                                particle_system.set_ghost(!particle_system.is_ghost(), 0.5);
+                            }
+                        }
                         KeyCode::KeyT => {
                             if let Some(particle_system) = &mut self.particle_system
                                 && let Some(renderer) = &self.renderer
                             {
-                                particle_system.restart(renderer.queue());
+                                particle_system.restart(
+                                    renderer.device(),
+                                    renderer.queue(),
+                                    self.restart_mode,
+                                );
+                            }
+                        }
+                        KeyCode::KeyY => {
+                            self.restart_mode = match self.restart_mode {
+                                RestartMode::Cold => RestartMode::Reseed,
+                                RestartMode::Reseed => RestartMode::Warm,
+                                RestartMode::Warm => RestartMode::Cold,
+                            };
+                        }
+                        _ => {
+                            // Digit k bursts 10^k particles
+                            if let Some(exponent) = digit_value(key_code)
+                                && exponent > 0
+                                && let Some(particle_system) = &mut self.particle_system
+                            {
+                                particle_system.burst(10u32.pow(exponent));
                             }
                         }
-                        _ => {}
                     }
                 }
             }
             WindowEvent::RedrawRequested => {
+                if self.renderer.as_ref().is_some_and(Renderer::is_device_lost)
+                    && !self.recover_lost_device()
+                {
+                    event_loop.exit();
+                    return;
+                }
+
                 let delta_time = self.timer.tick();
 
-                let speed = self.parameters.move_speed;
-                let scale = speed * delta_time;
-
-                if self.input_handler.is_key_pressed(KeyCode::KeyW) {
-                    self.camera.translate(self.camera.forward() * scale);
-                }
-                if self.input_handler.is_key_pressed(KeyCode::KeyA) {
-                    self.camera.translate(-self.camera.right() * scale);
-                }
-                if self.input_handler.is_key_pressed(KeyCode::KeyS) {
-                    self.camera.translate(-self.camera.forward() * scale);
-                }
-                if self.input_handler.is_key_pressed(KeyCode::KeyD) {
-                    self.camera.translate(self.camera.right() * scale);
-                }
+                let input = (!self.camera_locked).then_some(&self.input_handler);
+                self.camera_controller
+                    .update(&mut self.camera, input, delta_time);
 
                 let title = format!("Particle system ({} FPS)", (1.0 / delta_time) as u32);
                 window.set_title(title.as_str());
 
+                let size = window.inner_size();
+                let attractor = self.attractor_position(size.width, size.height);
+
                 if let Some(renderer) = &mut self.renderer {
                     match renderer.begin_frame() {
                         Ok(mut frame) => {
                             if let Some(particle_system) = &mut self.particle_system {
-                                particle_system.set_update_uniforms(
-                                    &mut frame,
-                                    UpdateUniforms {
-                                        gravity_center: (self.camera.position()
-                                            + self.camera.forward() * 20.0)
-                                            .extend(1.0)
-                                            .to_array(),
-                                        elapsed_time: particle_system.elapsed_time(),
-                                        delta_time,
-                                        padding: [0.0; 2],
-                                    },
-                                );
+                                particle_system.set_gravity_center(attractor);
+
+                                particle_system.set_view_proj(self.camera.view_proj());
+                                particle_system.set_camera_position(self.camera.position());
+
+                                particle_system.update(&mut frame, delta_time);
+
+                                // Only meaningful while repulsion bins particles
+                                if self.show_grid && particle_system.repulsion_radius() > 0.0 {
+                                    match particle_system
+                                        .read_grid_occupancy(renderer.device(), renderer.queue())
+                                    {
+                                        Ok(cells) => renderer.draw_grid(
+                                            &mut frame,
+                                            &cells,
+                                            particle_system.repulsion_radius(),
+                                            self.camera.view_proj(),
+                                        ),
+                                        Err(e) => eprintln!("Failed to read grid occupancy: {e}"),
+                                    }
+                                }
 
-                                particle_system.set_render_uniforms(
-                                    &mut frame,
-                                    RenderUniforms {
-                                        view_proj: self.camera.view_proj().to_cols_array_2d(),
-                                        color_start: [1.0, 0.0, 0.0, 0.2],
-                                        color_end: [0.0, 0.0, 1.0, 0.2],
-                                    },
-                                );
+                                // Replay finished bursts
+                                if particle_system.took_completion_event() {
+                                    particle_system.restart(
+                                        renderer.device(),
+                                        renderer.queue(),
+                                        self.restart_mode,
+                                    );
+                                }
 
-                                particle_system.update(&mut frame);
+                                if self.show_hud {
+                                    let state = if particle_system.is_paused() {
+                                        "Paused"
+                                    } else {
+                                        "Playing"
+                                    };
+
+                                    let lines = [
+                                        format!("FPS: {}", (1.0 / delta_time) as u32),
+                                        format!(
+                                            "Particles: {} / {}",
+                                            particle_system.alive_count(),
+                                            particle_system.max_particles()
+                                        ),
+                                        format!("State: {state}"),
+                                        format!("Restart: {:?}", self.restart_mode),
+                                        format!(
+                                            "GPU memory: {:.1} MB",
+                                            particle_system.gpu_memory_bytes() as f64
+                                                / (1024.0 * 1024.0)
+                                        ),
+                                    ];
+
+                                    renderer.draw_text(&mut frame, &lines);
+                                }
                             }
 
                             renderer.end_frame(frame);
@@ -285,3 +522,29 @@ impl ApplicationHandler for App {
         }
     }
 }
+
+/// Timestamped file in the working directory, so captures don't overwrite
+/// each other
+fn screenshot_path() -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis());
+
+    PathBuf::from(format!("screenshot-{millis}.png"))
+}
+
+fn digit_value(key_code: KeyCode) -> Option<u32> {
+    match key_code {
+        KeyCode::Digit0 => Some(0),
+        KeyCode::Digit1 => Some(1),
+        KeyCode::Digit2 => Some(2),
+        KeyCode::Digit3 => Some(3),
+        KeyCode::Digit4 => Some(4),
+        KeyCode::Digit5 => Some(5),
+        KeyCode::Digit6 => Some(6),
+        KeyCode::Digit7 => Some(7),
+        KeyCode::Digit8 => Some(8),
+        KeyCode::Digit9 => Some(9),
+        _ => None,
+    }
+}
@@ -1,3 +1,67 @@
+const MIN_FOV_Y: f32 = 1.0 * std::f32::consts::PI / 180.0;
+const MAX_FOV_Y: f32 = 179.0 * std::f32::consts::PI / 180.0;
+// Vertical FOV range `set_fov` zooms within
+const MIN_ZOOM_FOV: f32 = 10.0 * std::f32::consts::PI / 180.0;
+const MAX_ZOOM_FOV: f32 = 120.0 * std::f32::consts::PI / 180.0;
+const DEFAULT_PITCH_LIMIT: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CameraState {
+    pub position: glam::Vec3,
+    pub orientation: glam::Quat,
+}
+
+impl CameraState {
+    pub fn look_at(position: glam::Vec3, target: glam::Vec3, up: glam::Vec3) -> Self {
+        let view = glam::Mat4::look_at_rh(position, target, up);
+
+        Self {
+            position,
+            orientation: glam::Quat::from_mat4(&view.inverse()),
+        }
+    }
+
+    pub fn lerp(&self, other: &CameraState, t: f32) -> Self {
+        Self {
+            position: self.position.lerp(other.position, t),
+            orientation: self.orientation.slerp(other.orientation, t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ProjectionMode {
+    #[default]
+    Perspective,
+    /// Parallel projection showing `height` world units vertically
+    Orthographic { height: f32 },
+}
+
+/// Initial placement and lens of a camera
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraConfig {
+    pub position: glam::Vec3,
+    pub target: glam::Vec3,
+    pub up: glam::Vec3,
+    /// Horizontal field of view in radians
+    pub fov_x: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            position: glam::vec3(0.0, 0.0, 20.0),
+            target: glam::Vec3::ZERO,
+            up: glam::Vec3::Y,
+            fov_x: 120.0f32.to_radians(),
+            znear: 0.1,
+            zfar: 1000.0,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Camera {
     position: glam::Vec3,
@@ -7,6 +71,9 @@ pub struct Camera {
     up: glam::Vec3,
     znear: f32,
     zfar: f32,
+    projection_mode: ProjectionMode,
+    // Largest angle in radians the view can tilt above or below the horizon
+    pitch_limit: f32,
 }
 
 impl Camera {
@@ -19,10 +86,9 @@ impl Camera {
         znear: f32,
         zfar: f32,
     ) -> Self {
-        let fov_y = 2.0 * ((fov_x / 2.0).tan() / aspect).atan();
+        let fov_y = Self::clamp_fov_y(2.0 * ((fov_x / 2.0).tan() / aspect).atan());
 
-        let view = glam::Mat4::look_at_rh(position, target, up);
-        let orientation = glam::Quat::from_mat4(&view.inverse());
+        let orientation = CameraState::look_at(position, target, up).orientation;
 
         Self {
             position,
@@ -32,6 +98,30 @@ impl Camera {
             up,
             znear,
             zfar,
+            projection_mode: ProjectionMode::Perspective,
+            pitch_limit: DEFAULT_PITCH_LIMIT,
+        }
+    }
+
+    pub fn from_config(config: &CameraConfig, aspect: f32) -> Self {
+        Self::new(
+            config.position,
+            config.target,
+            config.up,
+            aspect,
+            config.fov_x,
+            config.znear,
+            config.zfar,
+        )
+    }
+
+    /// Keeps the vertical FOV inside (1°, 179°) so the projection never
+    /// degenerates. NaN falls back to the minimum.
+    fn clamp_fov_y(fov_y: f32) -> f32 {
+        if fov_y.is_nan() {
+            MIN_FOV_Y
+        } else {
+            fov_y.clamp(MIN_FOV_Y, MAX_FOV_Y)
         }
     }
 
@@ -55,7 +145,51 @@ impl Camera {
         self.position
     }
 
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            position: self.position,
+            orientation: self.orientation,
+        }
+    }
+
+    pub fn set_state(&mut self, state: CameraState) {
+        self.position = state.position;
+        self.orientation = state.orientation.normalize();
+    }
+
+    pub fn fov_y(&self) -> f32 {
+        self.fov_y
+    }
+
+    /// Sets the vertical FOV in radians, clamped to (1°, 179°). Returns the
+    /// value actually used, which differs from `fov_y` when it was clamped.
+    pub fn set_fov_y(&mut self, fov_y: f32) -> f32 {
+        self.fov_y = Self::clamp_fov_y(fov_y);
+        self.fov_y
+    }
+
+    /// Zooms to a vertical FOV of `fov_y` radians, clamped to 10°–120° with
+    /// NaN going to the narrowest. Returns the value actually used.
+    pub fn set_fov(&mut self, fov_y: f32) -> f32 {
+        let fov_y = if fov_y.is_nan() {
+            MIN_ZOOM_FOV
+        } else {
+            fov_y.clamp(MIN_ZOOM_FOV, MAX_ZOOM_FOV)
+        };
+
+        self.set_fov_y(fov_y)
+    }
+
+    /// Vertical FOV in radians
+    pub fn fov(&self) -> f32 {
+        self.fov_y
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
         self.aspect = width as f32 / height as f32;
     }
 
@@ -63,18 +197,153 @@ impl Camera {
         self.position += offset;
     }
 
+    /// Turns the view, a positive pitch looking down. The pitch stops at the
+    /// pitch limit so the view never flips over the poles.
     pub fn rotate(&mut self, delta_yaw: f32, delta_pitch: f32) {
+        let up = self.up().normalize_or(glam::Vec3::Y);
+        let elevation = self.forward().dot(up).clamp(-1.0, 1.0).asin();
+        // A state set past the limit isn't snapped back, it can only move
+        // towards the horizon
+        let limit = self.pitch_limit.max(elevation.abs());
+        let delta_pitch = elevation - (elevation - delta_pitch).clamp(-limit, limit);
+
         let yaw_quat = glam::Quat::from_axis_angle(self.up(), -delta_yaw);
         let pitch_quat = glam::Quat::from_axis_angle(self.right(), -delta_pitch);
 
         self.orientation = (yaw_quat * pitch_quat * self.orientation).normalize();
     }
 
+    /// Largest angle in radians `rotate` lets the view tilt above or below
+    /// the horizon, at most 90°
+    pub fn set_pitch_limit(&mut self, radians: f32) {
+        self.pitch_limit = radians.clamp(0.0, std::f32::consts::FRAC_PI_2);
+    }
+
+    pub fn pitch_limit(&self) -> f32 {
+        self.pitch_limit
+    }
+
+    pub fn projection_mode(&self) -> ProjectionMode {
+        self.projection_mode
+    }
+
+    pub fn set_projection_mode(&mut self, mode: ProjectionMode) {
+        self.projection_mode = mode;
+    }
+
+    /// Parallel projection showing `height` world units vertically, the width
+    /// following the aspect ratio
+    pub fn set_orthographic(&mut self, height: f32) {
+        self.projection_mode = ProjectionMode::Orthographic { height };
+    }
+
+    /// Perspective projection with a vertical FOV of `fov_y` radians
+    pub fn set_perspective(&mut self, fov_y: f32) {
+        self.set_fov_y(fov_y);
+        self.projection_mode = ProjectionMode::Perspective;
+    }
+
     pub fn projection(&self) -> glam::Mat4 {
-        glam::Mat4::perspective_rh(self.fov_y, self.aspect, self.znear, self.zfar)
+        match self.projection_mode {
+            ProjectionMode::Perspective => {
+                glam::Mat4::perspective_rh(self.fov_y, self.aspect, self.znear, self.zfar)
+            }
+            ProjectionMode::Orthographic { height } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * self.aspect;
+                glam::Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.znear,
+                    self.zfar,
+                )
+            }
+        }
+    }
+
+    /// Converts a depth buffer value back to the view-space distance along
+    /// the camera's forward axis.
+    pub fn linearize_depth(&self, depth: f32) -> f32 {
+        match self.projection_mode {
+            ProjectionMode::Perspective => {
+                self.znear * self.zfar / (self.zfar - depth * (self.zfar - self.znear))
+            }
+            ProjectionMode::Orthographic { .. } => self.znear + depth * (self.zfar - self.znear),
+        }
+    }
+
+    /// Ray through the pixel `(x, y)` of a `width` x `height` viewport, as an
+    /// origin on the near plane and a normalized direction.
+    pub fn screen_to_world_ray(
+        &self,
+        x: f32,
+        y: f32,
+        width: u32,
+        height: u32,
+    ) -> (glam::Vec3, glam::Vec3) {
+        let ndc_x = 2.0 * x / width.max(1) as f32 - 1.0;
+        let ndc_y = 1.0 - 2.0 * y / height.max(1) as f32;
+
+        let inverse = self.view_proj().inverse();
+        let near = inverse.project_point3(glam::vec3(ndc_x, ndc_y, 0.0));
+        let far = inverse.project_point3(glam::vec3(ndc_x, ndc_y, 1.0));
+
+        (near, (far - near).normalize_or(self.forward()))
     }
 
     pub fn view(&self) -> glam::Mat4 {
         glam::Mat4::from_rotation_translation(self.orientation, self.position).inverse()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_valid_projection(camera: &Camera) {
+        let m = camera.projection().to_cols_array_2d();
+        assert!(camera.projection().is_finite(), "{m:?}");
+        assert!(m[0][0] > 0.0 && m[1][1] > 0.0, "{m:?}");
+    }
+
+    #[test]
+    fn absurd_fov_keeps_projection_valid() {
+        for fov in [0.0, 1e6, f32::NAN, -1.0, f32::INFINITY] {
+            let camera = Camera::new(
+                glam::Vec3::Z,
+                glam::Vec3::ZERO,
+                glam::Vec3::Y,
+                1.5,
+                fov,
+                0.1,
+                100.0,
+            );
+            assert_valid_projection(&camera);
+
+            let mut camera = Camera::from_config(&CameraConfig::default(), 1.5);
+            let clamped = camera.set_fov_y(fov);
+            assert!((MIN_FOV_Y..=MAX_FOV_Y).contains(&clamped));
+            assert_eq!(clamped, camera.fov_y());
+            assert_valid_projection(&camera);
+        }
+    }
+
+    #[test]
+    fn set_fov_clamps_to_zoom_range() {
+        let mut camera = Camera::from_config(&CameraConfig::default(), 1.0);
+        assert_eq!(camera.set_fov(1.0), 1.0);
+        assert_eq!(camera.fov(), 1.0);
+        assert_eq!(camera.set_fov(0.01), MIN_ZOOM_FOV);
+        assert_eq!(camera.set_fov(3.0), MAX_ZOOM_FOV);
+        assert_eq!(camera.set_fov(f32::NAN), MIN_ZOOM_FOV);
+    }
+
+    #[test]
+    fn set_fov_y_reports_clamping() {
+        let mut camera = Camera::from_config(&CameraConfig::default(), 1.0);
+        assert_eq!(camera.set_fov_y(1.0), 1.0);
+        assert_eq!(camera.set_fov_y(10.0), MAX_FOV_Y);
+    }
+}
@@ -0,0 +1,160 @@
+use {
+    crate::{
+        camera::{Camera, CameraState},
+        input_handler::InputHandler,
+    },
+    std::{io, path::Path},
+    winit::keyboard::KeyCode,
+};
+
+pub struct CameraController {
+    path: Vec<CameraState>,
+    segment_duration: f32,
+    time: f32,
+    velocity: glam::Vec3,
+    max_speed: f32,
+    acceleration: f32,
+    damping: f32,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self::new(2.0)
+    }
+}
+
+impl CameraController {
+    /// `segment_duration` is the time in seconds spent between two keyframes
+    pub fn new(segment_duration: f32) -> Self {
+        Self {
+            path: Vec::new(),
+            segment_duration: segment_duration.max(f32::EPSILON),
+            time: 0.0,
+            velocity: glam::Vec3::ZERO,
+            max_speed: 10.0,
+            acceleration: 40.0,
+            damping: 8.0,
+        }
+    }
+
+    /// Loads a path with one keyframe per line, written as
+    /// `px py pz tx ty tz` (camera position then look-at target).
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn load_path(path: &Path) -> io::Result<Vec<CameraState>> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut states = Vec::new();
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .ok()
+                .filter(|values| values.len() == 6)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: expected `px py pz tx ty tz`", number + 1),
+                    )
+                })?;
+
+            states.push(CameraState::look_at(
+                glam::vec3(values[0], values[1], values[2]),
+                glam::vec3(values[3], values[4], values[5]),
+                glam::Vec3::Y,
+            ));
+        }
+
+        Ok(states)
+    }
+
+    pub fn set_target_path(&mut self, path: &[CameraState]) {
+        self.path = path.to_vec();
+        self.time = 0.0;
+    }
+
+    /// True while a path is loaded and has not been played to its end
+    pub fn is_active(&self) -> bool {
+        !self.path.is_empty() && self.time <= self.duration()
+    }
+
+    fn duration(&self) -> f32 {
+        self.path.len().saturating_sub(1) as f32 * self.segment_duration
+    }
+
+    /// Moves the camera `delta_time` seconds further along the path
+    pub fn advance(&mut self, camera: &mut Camera, delta_time: f32) {
+        if !self.is_active() {
+            return;
+        }
+
+        self.time += delta_time;
+
+        let last = self.path.len() - 1;
+        let progress = (self.time / self.segment_duration).min(last as f32);
+        let index = (progress.floor() as usize).min(last);
+        let next = (index + 1).min(last);
+
+        let state = self.path[index].lerp(&self.path[next], progress - index as f32);
+        camera.set_state(state);
+    }
+
+    /// Plays the loaded path if there is one, otherwise moves the camera with
+    /// WASD. The velocity accelerates towards the held direction and decays
+    /// once the keys are released. Without `input` no key counts as held, so
+    /// the camera glides to a stop.
+    pub fn update(&mut self, camera: &mut Camera, input: Option<&InputHandler>, delta_time: f32) {
+        if self.is_active() {
+            self.velocity = glam::Vec3::ZERO;
+            self.advance(camera, delta_time);
+            return;
+        }
+
+        let direction = input.map_or(glam::Vec3::ZERO, |input| {
+            let axis = |positive, negative| {
+                input.is_key_pressed(positive) as i32 as f32
+                    - input.is_key_pressed(negative) as i32 as f32
+            };
+
+            camera.forward() * axis(KeyCode::KeyW, KeyCode::KeyS)
+                + camera.right() * axis(KeyCode::KeyD, KeyCode::KeyA)
+        });
+
+        if direction == glam::Vec3::ZERO {
+            self.velocity *= (-self.damping * delta_time).exp();
+        } else {
+            let target = direction.normalize() * self.max_speed;
+            let change = target - self.velocity;
+            let step = self.acceleration * delta_time;
+            self.velocity = if change.length() <= step {
+                target
+            } else {
+                self.velocity + change.normalize() * step
+            };
+        }
+
+        camera.translate(self.velocity * delta_time);
+    }
+
+    /// Top speed in units per second while a movement key is held
+    pub fn set_max_speed(&mut self, max_speed: f32) {
+        self.max_speed = max_speed.max(0.0);
+    }
+
+    /// `acceleration` is how fast, in units per second squared, the velocity
+    /// turns towards the held direction. `damping` is the exponential decay
+    /// rate once the keys are released.
+    pub fn set_smoothing(&mut self, acceleration: f32, damping: f32) {
+        self.acceleration = acceleration.max(0.0);
+        self.damping = damping.max(0.0);
+    }
+
+    pub fn velocity(&self) -> glam::Vec3 {
+        self.velocity
+    }
+}
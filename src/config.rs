@@ -0,0 +1,484 @@
+use {
+    crate::{
+        camera::CameraConfig,
+        particle_system::{
+            BlendMode, ParticleEmissionMode, ParticleEmissionShape, ParticlePrecision,
+            ParticleSystem, ParticleSystemInfo,
+        },
+    },
+    std::{
+        fmt,
+        path::{Path, PathBuf},
+    },
+};
+
+// Storage buffer bindings are addressed with 32 bits on every backend
+const MAX_BUFFER_SIZE: u64 = u32::MAX as u64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+        expected: &'static str,
+    },
+    ConfigFile {
+        path: PathBuf,
+        message: String,
+    },
+    InvalidLifetime(f32),
+    /// Continuous and pulsed emission never stop, so particles must die
+    InfiniteContinuousLifetime,
+    CapacityTooLarge {
+        requested: u64,
+        supported: u64,
+    },
+    /// Horizontal field of view in degrees
+    FovOutOfRange(f32),
+    InvalidClipPlanes {
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownOption(option) => write!(f, "Unknown option: {option}"),
+            ConfigError::MissingValue(option) => write!(f, "{option} expects a value"),
+            ConfigError::InvalidValue {
+                option,
+                value,
+                expected,
+            } => write!(
+                f,
+                "Invalid value '{value}' for {option}, expected {expected}"
+            ),
+            ConfigError::ConfigFile { path, message } => {
+                write!(f, "Failed to read config {}: {message}", path.display())
+            }
+            ConfigError::InvalidLifetime(lifetime) => {
+                write!(f, "Lifetime must be positive, got {lifetime}")
+            }
+            ConfigError::InfiniteContinuousLifetime => write!(
+                f,
+                "Continuous and pulsed emission need a finite --lifetime, or the buffers would have to be unbounded"
+            ),
+            ConfigError::CapacityTooLarge {
+                requested,
+                supported,
+            } => write!(
+                f,
+                "Particle buffers would need {requested} bytes but at most {supported} are supported, lower the emission count or lifetime"
+            ),
+            ConfigError::FovOutOfRange(degrees) => {
+                write!(
+                    f,
+                    "Field of view must be between 1 and 179 degrees, got {degrees}"
+                )
+            }
+            ConfigError::InvalidClipPlanes { znear, zfar } => write!(
+                f,
+                "Clip planes must satisfy 0 < near < far, got near {znear} and far {zfar}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Everything the demo can be configured with before any GPU resource exists
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub particle_system: ParticleSystemInfo,
+    pub camera: CameraConfig,
+    pub flythrough: Option<PathBuf>,
+    pub absorb_radius: f32,
+    /// Repulsion radius and strength, a radius of zero turns it off
+    pub repulsion: (f32, f32),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            particle_system: ParticleSystemInfo {
+                position: glam::Vec3::ZERO,
+                shape: ParticleEmissionShape::Sphere,
+                mode: ParticleEmissionMode::Burst(100000),
+                lifetime: f32::INFINITY,
+                lifetime_variance: 0.0,
+                precision: ParticlePrecision::Full,
+                depth_bias: wgpu::DepthBiasState::default(),
+                blend_mode: BlendMode::Additive,
+            },
+            camera: CameraConfig::default(),
+            flythrough: None,
+            absorb_radius: 0.0,
+            repulsion: (0.0, 0.0),
+        }
+    }
+}
+
+impl Config {
+    /// Parses command line options over the defaults and validates the
+    /// result. `--config <path>` reads more options from a file, one
+    /// `option value` pair per line without the leading dashes.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(option) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| ConfigError::MissingValue(option.clone()))?;
+
+            if option == "--config" {
+                config.load_file(Path::new(&value))?;
+            } else {
+                config.apply(&option, &value)?;
+            }
+        }
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn load_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| ConfigError::ConfigFile {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (option, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let option = format!("--{option}");
+            let value = value.trim();
+
+            if value.is_empty() {
+                return Err(ConfigError::MissingValue(option));
+            }
+
+            self.apply(&option, value)?;
+        }
+
+        Ok(())
+    }
+
+    fn apply(&mut self, option: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = |expected| ConfigError::InvalidValue {
+            option: option.to_string(),
+            value: value.to_string(),
+            expected,
+        };
+
+        match option {
+            "--mode" => {
+                self.particle_system.mode = parse_mode(value)
+                    .ok_or_else(|| invalid("burst:N, continuous:N or pulsed:N,SECONDS"))?;
+            }
+            "--shape" => {
+                self.particle_system.shape = parse_shape(value).ok_or_else(|| {
+                    invalid(
+                        "point, sphere, cube, line:X,Y,Z:X,Y,Z, helix:RADIUS,PITCH,TURNS, spiral:RADIUS,TURNS, cone:DEGREES:X,Y,Z or disk:INNER,OUTER:X,Y,Z",
+                    )
+                })?;
+            }
+            "--lifetime" => {
+                self.particle_system.lifetime = match value {
+                    "inf" => f32::INFINITY,
+                    _ => value.parse().map_err(|_| invalid("seconds or inf"))?,
+                };
+            }
+            "--lifetime-variance" => {
+                self.particle_system.lifetime_variance = value
+                    .parse()
+                    .ok()
+                    .filter(|variance: &f32| *variance >= 0.0)
+                    .ok_or_else(|| invalid("non-negative seconds"))?;
+            }
+            "--position" => {
+                self.particle_system.position =
+                    parse_vec3(value).ok_or_else(|| invalid("x,y,z"))?;
+            }
+            "--absorb" => {
+                self.absorb_radius = value
+                    .parse()
+                    .ok()
+                    .filter(|radius: &f32| *radius >= 0.0)
+                    .ok_or_else(|| invalid("a non-negative radius"))?;
+            }
+            "--repulsion" => {
+                self.repulsion = parse_pair(value)
+                    .filter(|&(radius, strength)| radius >= 0.0 && strength >= 0.0)
+                    .ok_or_else(|| invalid("non-negative radius,strength"))?;
+            }
+            "--camera-position" => {
+                self.camera.position = parse_vec3(value).ok_or_else(|| invalid("x,y,z"))?;
+            }
+            "--camera-target" => {
+                self.camera.target = parse_vec3(value).ok_or_else(|| invalid("x,y,z"))?;
+            }
+            "--fov" => {
+                let degrees: f32 = value.parse().map_err(|_| invalid("degrees"))?;
+                self.camera.fov_x = degrees.to_radians();
+            }
+            "--clip" => {
+                (self.camera.znear, self.camera.zfar) =
+                    parse_pair(value).ok_or_else(|| invalid("near,far"))?;
+            }
+            "--precision" => {
+                self.particle_system.precision =
+                    parse_precision(value).ok_or_else(|| invalid(PRECISIONS))?;
+            }
+            "--flythrough" => self.flythrough = Some(PathBuf::from(value)),
+            _ => return Err(ConfigError::UnknownOption(option.to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// Checks the settings that would otherwise fail, or be silently
+    /// adjusted, once the window and GPU resources exist
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let info = &self.particle_system;
+
+        if info.lifetime.is_nan() || info.lifetime <= 0.0 {
+            return Err(ConfigError::InvalidLifetime(info.lifetime));
+        }
+
+        if info.lifetime.is_infinite() && !matches!(info.mode, ParticleEmissionMode::Burst(_)) {
+            return Err(ConfigError::InfiniteContinuousLifetime);
+        }
+
+        let requested = ParticleSystem::required_buffer_size(info);
+        if requested > MAX_BUFFER_SIZE {
+            return Err(ConfigError::CapacityTooLarge {
+                requested,
+                supported: MAX_BUFFER_SIZE,
+            });
+        }
+
+        let fov = self.camera.fov_x.to_degrees();
+        if !(1.0..=179.0).contains(&fov) {
+            return Err(ConfigError::FovOutOfRange(fov));
+        }
+
+        let (znear, zfar) = (self.camera.znear, self.camera.zfar);
+        if !(znear > 0.0 && znear < zfar) {
+            return Err(ConfigError::InvalidClipPlanes { znear, zfar });
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_floats(value: &str) -> Option<Vec<f32>> {
+    value
+        .split(',')
+        .map(|component| component.trim().parse::<f32>().ok())
+        .collect()
+}
+
+fn parse_vec3(value: &str) -> Option<glam::Vec3> {
+    match parse_floats(value)?[..] {
+        [x, y, z] => Some(glam::vec3(x, y, z)),
+        _ => None,
+    }
+}
+
+fn parse_pair(value: &str) -> Option<(f32, f32)> {
+    match parse_floats(value)?[..] {
+        [a, b] => Some((a, b)),
+        _ => None,
+    }
+}
+
+fn parse_mode(value: &str) -> Option<ParticleEmissionMode> {
+    let (kind, parameters) = value.split_once(':')?;
+
+    match kind {
+        "burst" => Some(ParticleEmissionMode::Burst(parameters.parse().ok()?)),
+        "continuous" => Some(ParticleEmissionMode::Continuous(parameters.parse().ok()?)),
+        "pulsed" => {
+            let (count, interval) = parameters.split_once(',')?;
+            Some(ParticleEmissionMode::PulsedBurst {
+                count: count.trim().parse().ok()?,
+                interval: interval.trim().parse().ok()?,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(feature = "half-precision")]
+const PRECISIONS: &str = "full or half";
+#[cfg(not(feature = "half-precision"))]
+const PRECISIONS: &str = "full, half needs the half-precision feature";
+
+fn parse_precision(value: &str) -> Option<ParticlePrecision> {
+    match value {
+        "full" => Some(ParticlePrecision::Full),
+        #[cfg(feature = "half-precision")]
+        "half" => Some(ParticlePrecision::Half),
+        _ => None,
+    }
+}
+
+fn parse_shape(value: &str) -> Option<ParticleEmissionShape> {
+    let (kind, parameters) = value.split_once(':').unwrap_or((value, ""));
+
+    match kind {
+        "point" => Some(ParticleEmissionShape::Point),
+        "sphere" => Some(ParticleEmissionShape::Sphere),
+        "cube" => Some(ParticleEmissionShape::Cube),
+        "line" => {
+            let (start, end) = parameters.split_once(':')?;
+            Some(ParticleEmissionShape::Line {
+                start: parse_vec3(start)?,
+                end: parse_vec3(end)?,
+            })
+        }
+        "helix" => match parse_floats(parameters)?[..] {
+            [radius, pitch, turns] => Some(ParticleEmissionShape::Helix {
+                radius,
+                pitch,
+                turns,
+            }),
+            _ => None,
+        },
+        "spiral" => {
+            let (radius, turns) = parse_pair(parameters)?;
+            Some(ParticleEmissionShape::Spiral { radius, turns })
+        }
+        "cone" => {
+            let (angle, direction) = parameters.split_once(':')?;
+            Some(ParticleEmissionShape::Cone {
+                angle: angle.trim().parse::<f32>().ok()?.to_radians(),
+                direction: parse_vec3(direction)?,
+            })
+        }
+        "disk" => {
+            let (radii, normal) = parameters.split_once(':')?;
+            let (inner_radius, outer_radius) = parse_pair(radii)?;
+            Some(ParticleEmissionShape::Disk {
+                inner_radius,
+                outer_radius,
+                normal: parse_vec3(normal)?,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn unknown_option_is_rejected() {
+        assert_eq!(
+            parse(&["--colour", "red"]).unwrap_err(),
+            ConfigError::UnknownOption("--colour".to_string())
+        );
+    }
+
+    #[test]
+    fn option_without_value_is_rejected() {
+        assert_eq!(
+            parse(&["--fov"]).unwrap_err(),
+            ConfigError::MissingValue("--fov".to_string())
+        );
+    }
+
+    #[test]
+    fn fov_outside_range_is_rejected() {
+        assert!(matches!(
+            parse(&["--fov", "0.5"]),
+            Err(ConfigError::FovOutOfRange(_))
+        ));
+        assert!(matches!(
+            parse(&["--fov", "180"]),
+            Err(ConfigError::FovOutOfRange(_))
+        ));
+        assert!(parse(&["--fov", "90"]).is_ok());
+    }
+
+    #[test]
+    fn continuous_emission_needs_finite_lifetime() {
+        assert_eq!(
+            parse(&["--mode", "continuous:1000"]).unwrap_err(),
+            ConfigError::InfiniteContinuousLifetime
+        );
+        assert!(parse(&["--mode", "continuous:1000", "--lifetime", "2"]).is_ok());
+    }
+
+    #[test]
+    fn clip_planes_must_be_ordered() {
+        assert_eq!(
+            parse(&["--clip", "10,1"]).unwrap_err(),
+            ConfigError::InvalidClipPlanes {
+                znear: 10.0,
+                zfar: 1.0
+            }
+        );
+        assert!(matches!(
+            parse(&["--clip", "0,1"]),
+            Err(ConfigError::InvalidClipPlanes { .. })
+        ));
+    }
+
+    #[test]
+    fn negative_or_nan_forces_are_rejected() {
+        for args in [
+            ["--absorb", "-1"],
+            ["--absorb", "NaN"],
+            ["--repulsion", "-1,1"],
+            ["--repulsion", "1,-1"],
+            ["--repulsion", "NaN,1"],
+        ] {
+            assert!(
+                matches!(parse(&args), Err(ConfigError::InvalidValue { .. })),
+                "{args:?} was accepted"
+            );
+        }
+
+        let config = parse(&["--absorb", "0.5", "--repulsion", "2,3"]).unwrap();
+        assert_eq!(config.absorb_radius, 0.5);
+        assert_eq!(config.repulsion, (2.0, 3.0));
+    }
+
+    #[test]
+    fn config_file_overrides_defaults() {
+        let path = std::env::temp_dir().join(format!("particle_system_{}.cfg", std::process::id()));
+        std::fs::write(
+            &path,
+            "# Slow fountain\nmode continuous:500\nlifetime 3\n\nclip 0.5,50\n",
+        )
+        .unwrap();
+
+        let config = parse(&["--config", path.to_str().unwrap()]);
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        assert_eq!(
+            config.particle_system.mode,
+            ParticleEmissionMode::Continuous(500)
+        );
+        assert_eq!(config.particle_system.lifetime, 3.0);
+        assert_eq!((config.camera.znear, config.camera.zfar), (0.5, 50.0));
+        // Options the file leaves out keep their defaults
+        assert_eq!(config.camera.fov_x, CameraConfig::default().fov_x);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{particle_system::GridCell, renderer::RenderContext};
+
+// Only the fullest cells are drawn past this
+const MAX_CELLS: usize = 4096;
+const VERTICES_PER_CELL: usize = 24;
+
+// Corner pairs of the 12 cube edges, a corner's bits select max over min per axis
+const EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GridVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GridUniforms {
+    view_proj: [[f32; 4]; 4],
+}
+
+/// Wireframe of grid cells, colored from blue to red by particle count
+pub struct GridOverlay {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    uniforms_buffer: wgpu::Buffer,
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl GridOverlay {
+    /// Without `depth_test` the pipeline has no depth state, for frames drawn
+    /// without a depth buffer. `sample_count` must match the scene target.
+    pub fn new(
+        device: &wgpu::Device,
+        target_format: wgpu::TextureFormat,
+        depth_test: bool,
+        sample_count: u32,
+    ) -> Self {
+        let uniforms_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Grid Uniforms Buffer"),
+            size: std::mem::size_of::<GridUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Grid Vertex Buffer"),
+            size: (MAX_CELLS * VERTICES_PER_CELL * std::mem::size_of::<GridVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (pipeline, bind_group) = Self::create_pipeline(
+            device,
+            target_format,
+            depth_test,
+            sample_count,
+            &uniforms_buffer,
+        );
+
+        Self {
+            pipeline,
+            bind_group,
+            uniforms_buffer,
+            vertex_buffer,
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        target_format: wgpu::TextureFormat,
+        depth_test: bool,
+        sample_count: u32,
+        uniforms_buffer: &wgpu::Buffer,
+    ) -> (wgpu::RenderPipeline, wgpu::BindGroup) {
+        let grid_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Grid Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/grid.wgsl").into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Grid Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Grid Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniforms_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Grid Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Grid Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &grid_shader,
+                entry_point: Some("vs_main"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<GridVertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x3,
+                        1 => Float32x4,
+                    ],
+                }],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &grid_shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: depth_test.then(|| wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        (pipeline, bind_group)
+    }
+
+    fn push_cell(vertices: &mut Vec<GridVertex>, min: glam::Vec3, size: f32, color: [f32; 4]) {
+        let corner = |index: usize| {
+            let offset = glam::vec3(
+                (index & 1) as f32,
+                ((index >> 1) & 1) as f32,
+                ((index >> 2) & 1) as f32,
+            );
+            (min + offset * size).to_array()
+        };
+
+        for (start, end) in EDGES {
+            vertices.push(GridVertex {
+                position: corner(start),
+                color,
+            });
+            vertices.push(GridVertex {
+                position: corner(end),
+                color,
+            });
+        }
+    }
+
+    /// Draws the outline of each of `cells`, `cell_size` wide, into the scene
+    /// target. Cells shift from blue to red as they approach the fullest one.
+    pub fn draw(
+        &self,
+        context: &mut RenderContext,
+        cells: &[GridCell],
+        cell_size: f32,
+        view_proj: glam::Mat4,
+    ) {
+        let max_count = cells.iter().map(|cell| cell.count).max().unwrap_or(0);
+        if max_count == 0 || cell_size <= 0.0 {
+            return;
+        }
+
+        let mut vertices = Vec::with_capacity(cells.len().min(MAX_CELLS) * VERTICES_PER_CELL);
+        for cell in cells.iter().take(MAX_CELLS) {
+            let t = cell.count as f32 / max_count as f32;
+            let color = [t, 0.2, 1.0 - t, 0.3 + 0.7 * t];
+            Self::push_cell(
+                &mut vertices,
+                cell.cell.as_vec3() * cell_size,
+                cell_size,
+                color,
+            );
+        }
+
+        let uniforms = GridUniforms {
+            view_proj: view_proj.to_cols_array_2d(),
+        };
+        context
+            .queue()
+            .write_buffer(&self.uniforms_buffer, 0, bytemuck::bytes_of(&uniforms));
+        context
+            .queue()
+            .write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let view = context.view().clone();
+        let resolve_target = context.resolve_target().cloned();
+        let depth_view = context.depth_view().cloned();
+        let (color_load, depth_load) = context.load_ops();
+        let mut pass = context
+            .encoder_mut()
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Grid Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: resolve_target.as_ref(),
+                    depth_slice: None,
+                    ops: wgpu::Operations {
+                        load: color_load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: depth_view.as_ref().map(|view| {
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: depth_load,
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
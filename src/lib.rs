@@ -0,0 +1,10 @@
+pub mod app;
+pub mod camera;
+pub mod camera_controller;
+pub mod config;
+mod grid_overlay;
+pub mod input_handler;
+pub mod particle_system;
+pub mod renderer;
+mod text_renderer;
+pub mod timer;
@@ -1,16 +1,17 @@
-mod app;
-mod camera;
-mod input_handler;
-mod particle_system;
-mod renderer;
-mod timer;
-
 use {
-    crate::app::App,
+    particle_system::{app::App, camera_controller::CameraController, config::Config},
     winit::event_loop::{ControlFlow, EventLoop},
 };
 
 fn main() {
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+
     let event_loop = match EventLoop::new() {
         Ok(event_loop) => event_loop,
         Err(e) => {
@@ -21,6 +22,17 @@ fn main() {
 
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let mut app = App::default();
+    let flythrough = config.flythrough.clone();
+    let mut app = App::new(config);
+
+    if let Some(path) = flythrough {
+        match CameraController::load_path(&path) {
+            Ok(states) => app.set_flythrough(&states),
+            Err(e) => {
+                eprintln!("Failed to load flythrough {}: {e}", path.display());
+                return;
+            }
+        }
+    }
     let _ = event_loop.run_app(&mut app);
 }
@@ -1,4 +1,40 @@
-use {crate::renderer::RenderContext, std::time::Instant, wgpu::wgt::DrawIndirectArgs};
+use {
+    crate::{
+        config::Config,
+        renderer::{MAX_POINT_SIZE, RenderContext},
+    },
+    std::{
+        io,
+        path::Path,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+    wgpu::wgt::DrawIndirectArgs,
+};
+
+// Render stride automatic level of detail reaches at its far distance
+const MAX_LOD_STRIDE: f32 = 16.0;
+
+/// Attractors `set_attractors` keeps, the rest are dropped
+pub const MAX_ATTRACTORS: usize = 64;
+
+// Mass of the attractor `set_gravity_center` places
+const GRAVITY_CENTER_MASS: f32 = 10.0;
+
+// Fixed timestep updates run per frame at most, the time they can't catch
+// up on is dropped
+const MAX_FIXED_STEPS: u32 = 8;
+
+// Buckets in the spatial hash used for particle repulsion
+const GRID_CELLS: u64 = 1 << 18;
+
+// Offset between the uniforms of two bitonic sort stages, the largest uniform
+// offset alignment WebGPU allows
+const SORT_STAGE_STRIDE: u64 = 256;
+
+// Where the id counter shared by the emit and update passes sits in the
+// counter buffer, right after the indirect draw args
+const NEXT_ID_OFFSET: u64 = std::mem::size_of::<DrawIndirectArgs>() as u64;
 
 #[repr(C, align(16))]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -8,15 +44,290 @@ pub struct Particle {
     pub mass: f32,
     pub lifetime: f32,
     pub age: f32,
-    pub padding: [f32; 1],
+    pub id: u32,
+    pub color: [f32; 4],
+    /// 0 for emitted particles, one more than the parent for spawned children
+    pub generation: u32,
+    /// Kelvin, cooled every frame and shown by `RenderMode::Blackbody`
+    pub temperature: f32,
+    pub padding: [u32; 2],
+}
+
+/// Storage layout of `Particle` at half precision, matching particle_half.wgsl
+#[cfg(feature = "half-precision")]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PackedParticle {
+    position: [half::f16; 4],
+    velocity: [half::f16; 4],
+    mass: f32,
+    lifetime: f32,
+    age: f32,
+    id: u32,
+    color: [half::f16; 4],
+    generation: u32,
+    temperature: f32,
+}
+
+#[cfg(feature = "half-precision")]
+impl From<&Particle> for PackedParticle {
+    fn from(particle: &Particle) -> Self {
+        let pack = |v: [f32; 4]| v.map(half::f16::from_f32);
+
+        Self {
+            position: pack(particle.position),
+            velocity: pack(particle.velocity),
+            mass: particle.mass,
+            lifetime: particle.lifetime,
+            age: particle.age,
+            id: particle.id,
+            color: pack(particle.color),
+            generation: particle.generation,
+            temperature: particle.temperature,
+        }
+    }
+}
+
+#[cfg(feature = "half-precision")]
+impl From<&PackedParticle> for Particle {
+    fn from(packed: &PackedParticle) -> Self {
+        let unpack = |v: [half::f16; 4]| v.map(half::f16::to_f32);
+
+        Self {
+            position: unpack(packed.position),
+            velocity: unpack(packed.velocity),
+            mass: packed.mass,
+            lifetime: packed.lifetime,
+            age: packed.age,
+            id: packed.id,
+            color: unpack(packed.color),
+            generation: packed.generation,
+            temperature: packed.temperature,
+            padding: [0; 2],
+        }
+    }
+}
+
+/// How particles are stored in the GPU buffers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParticlePrecision {
+    #[default]
+    Full,
+    /// Position, velocity and color as 16-bit floats, 48 bytes per particle
+    /// instead of 80. Positions are only accurate to 0.5 units past 512 units
+    /// from the origin, and small forces can round away over a frame.
+    #[cfg(feature = "half-precision")]
+    Half,
+}
+
+impl ParticlePrecision {
+    /// Bytes per particle in the particle buffers
+    pub fn particle_size(self) -> u64 {
+        match self {
+            ParticlePrecision::Full => std::mem::size_of::<Particle>() as u64,
+            #[cfg(feature = "half-precision")]
+            ParticlePrecision::Half => std::mem::size_of::<PackedParticle>() as u64,
+        }
+    }
+
+    // Defines Particle, PackedParticle and the conversions between them
+    fn shader_prelude(self) -> &'static str {
+        match self {
+            ParticlePrecision::Full => include_str!("../shaders/particle.wgsl"),
+            #[cfg(feature = "half-precision")]
+            ParticlePrecision::Half => include_str!("../shaders/particle_half.wgsl"),
+        }
+    }
+
+    fn encode_particles(self, particles: &[Particle]) -> Vec<u8> {
+        match self {
+            ParticlePrecision::Full => bytemuck::cast_slice(particles).to_vec(),
+            #[cfg(feature = "half-precision")]
+            ParticlePrecision::Half => {
+                let packed: Vec<PackedParticle> = particles.iter().map(Into::into).collect();
+                bytemuck::cast_slice(&packed).to_vec()
+            }
+        }
+    }
+
+    fn decode_particles(self, data: &[u8]) -> Vec<Particle> {
+        match self {
+            ParticlePrecision::Full => bytemuck::pod_collect_to_vec(data),
+            #[cfg(feature = "half-precision")]
+            ParticlePrecision::Half => {
+                let packed: Vec<PackedParticle> = bytemuck::pod_collect_to_vec(data);
+                packed.iter().map(Into::into).collect()
+            }
+        }
+    }
 }
 
-#[allow(unused)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ParticleEmissionShape {
     Point,
     Sphere,
     Cube,
+    /// Particles are spread along the segment between `start` and `end`,
+    /// both relative to the emitter position
+    Line {
+        start: glam::Vec3,
+        end: glam::Vec3,
+    },
+    /// Particles are spread along a helix around the Y axis, rising `pitch`
+    /// per turn and centered on the emitter
+    Helix {
+        radius: f32,
+        pitch: f32,
+        turns: f32,
+    },
+    /// Particles are spread along a flat spiral in the XZ plane, its radius
+    /// growing from zero to `radius` over `turns` turns
+    Spiral {
+        radius: f32,
+        turns: f32,
+    },
+    /// Particles leave the emitter in random directions at most `angle`
+    /// radians away from `direction`, whose length is their speed
+    Cone {
+        angle: f32,
+        direction: glam::Vec3,
+    },
+    /// Particles are spread evenly over the flat ring between `inner_radius`
+    /// and `outer_radius` facing `normal`, a full disk with an inner radius
+    /// of zero
+    Disk {
+        inner_radius: f32,
+        outer_radius: f32,
+        normal: glam::Vec3,
+    },
+}
+
+impl ParticleEmissionShape {
+    // Value of `shape` in `emit.wgsl`
+    fn to_uniforms(self) -> u32 {
+        match self {
+            ParticleEmissionShape::Point => 0,
+            ParticleEmissionShape::Sphere => 1,
+            ParticleEmissionShape::Cube => 2,
+            ParticleEmissionShape::Line { .. } => 3,
+            ParticleEmissionShape::Helix { .. } => 4,
+            ParticleEmissionShape::Spiral { .. } => 5,
+            ParticleEmissionShape::Cone { .. } => 6,
+            ParticleEmissionShape::Disk { .. } => 7,
+        }
+    }
+
+    /// Interpolates the parameters of two shapes of the same kind, different
+    /// kinds switch over halfway
+    fn lerp(self, other: Self, t: f32) -> Self {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+
+        match (self, other) {
+            (
+                ParticleEmissionShape::Line { start, end },
+                ParticleEmissionShape::Line {
+                    start: other_start,
+                    end: other_end,
+                },
+            ) => ParticleEmissionShape::Line {
+                start: start.lerp(other_start, t),
+                end: end.lerp(other_end, t),
+            },
+            (
+                ParticleEmissionShape::Helix {
+                    radius,
+                    pitch,
+                    turns,
+                },
+                ParticleEmissionShape::Helix {
+                    radius: other_radius,
+                    pitch: other_pitch,
+                    turns: other_turns,
+                },
+            ) => ParticleEmissionShape::Helix {
+                radius: mix(radius, other_radius),
+                pitch: mix(pitch, other_pitch),
+                turns: mix(turns, other_turns),
+            },
+            (
+                ParticleEmissionShape::Spiral { radius, turns },
+                ParticleEmissionShape::Spiral {
+                    radius: other_radius,
+                    turns: other_turns,
+                },
+            ) => ParticleEmissionShape::Spiral {
+                radius: mix(radius, other_radius),
+                turns: mix(turns, other_turns),
+            },
+            (
+                ParticleEmissionShape::Cone { angle, direction },
+                ParticleEmissionShape::Cone {
+                    angle: other_angle,
+                    direction: other_direction,
+                },
+            ) => ParticleEmissionShape::Cone {
+                angle: mix(angle, other_angle),
+                direction: direction.lerp(other_direction, t),
+            },
+            (
+                ParticleEmissionShape::Disk {
+                    inner_radius,
+                    outer_radius,
+                    normal,
+                },
+                ParticleEmissionShape::Disk {
+                    inner_radius: other_inner_radius,
+                    outer_radius: other_outer_radius,
+                    normal: other_normal,
+                },
+            ) => ParticleEmissionShape::Disk {
+                inner_radius: mix(inner_radius, other_inner_radius),
+                outer_radius: mix(outer_radius, other_outer_radius),
+                normal: normal.lerp(other_normal, t),
+            },
+            _ if t < 0.5 => self,
+            _ => other,
+        }
+    }
+}
+
+/// Source of the random numbers placing emitted particles
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParticleSampling {
+    /// Independent hashed random numbers per particle
+    #[default]
+    Random,
+    /// Halton low-discrepancy sequence over the emission order, covering the
+    /// shape more evenly
+    Halton,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ParticleEmissionDistribution {
+    /// Particles are spread evenly over the shape
+    #[default]
+    Uniform,
+    /// Particles are normally distributed around the emitter, denser at the center
+    Gaussian { sigma: f32 },
+}
+
+impl ParticleEmissionDistribution {
+    fn to_uniforms(self) -> (u32, f32) {
+        match self {
+            ParticleEmissionDistribution::Uniform => (0, 0.0),
+            ParticleEmissionDistribution::Gaussian { sigma } => (1, sigma),
+        }
+    }
+}
+
+/// How a velocity field acts on the particles inside its bounds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VelocityFieldMode {
+    /// Particles move with the field, ignoring every other force
+    #[default]
+    Replace,
+    /// The field is an acceleration added to the other forces
+    Add,
 }
 
 #[repr(C, align(16))]
@@ -27,15 +338,59 @@ pub struct EmitUniforms {
     pub shape: u32,
     pub lifetime: f32,
     pub elapsed_time: f32,
+    pub sample_base: u32,
+    pub shell_thickness: f32,
+    pub distribution: u32,
+    pub sigma: f32,
+    pub tint: [f32; 4],
+    pub line_start: [f32; 4],
+    pub line_end: [f32; 4],
+    pub extents: [f32; 4],
+    pub sampling: u32,
+    pub debug: u32,
+    pub interpolate: u32,
+    pub age_stagger: f32,
+    pub previous_position: [f32; 4],
+    pub orientation: [f32; 4],
+    pub curve: [f32; 4],
+    pub temperature: f32,
+    pub project_color: u32,
+    pub seed: u32,
+    pub lifetime_variance: f32,
+    pub projection_view_proj: [[f32; 4]; 4],
+    pub cone: [f32; 4],
+    pub disk_radii: [f32; 4],
+    pub disk_normal: [f32; 4],
+    pub initial_speed: f32,
+    pub speed_variance: f32,
+    pub override_speed: u32,
+    pub padding: u32,
 }
 
 #[repr(C, align(16))]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct UpdateUniforms {
-    pub gravity_center: [f32; 4],
     pub elapsed_time: f32,
     pub delta_time: f32,
-    pub padding: [f32; 2],
+    pub repulsion_radius: f32,
+    pub repulsion_strength: f32,
+    pub bounds_min: [f32; 4],
+    pub bounds_max: [f32; 4],
+    pub bounds_mode: u32,
+    pub debug: u32,
+    pub field_mode: u32,
+    pub child_count: u32,
+    pub field_min: [f32; 4],
+    pub field_max: [f32; 4],
+    pub child_speed: f32,
+    pub cooling_rate: f32,
+    pub absorb_radius: f32,
+    pub attractor_count: u32,
+    pub drag: f32,
+    pub turbulence_strength: f32,
+    pub turbulence_scale: f32,
+    pub padding: u32,
+    pub wind: [f32; 4],
 }
 
 #[repr(C, align(16))]
@@ -44,13 +399,439 @@ pub struct RenderUniforms {
     pub view_proj: [[f32; 4]; 4],
     pub color_start: [f32; 4],
     pub color_end: [f32; 4],
+    pub mode: u32,
+    pub ghost_fade: f32,
+    pub alpha_start: f32,
+    pub alpha_end: f32,
+    pub viewport_size: [f32; 2],
+    pub point_size: f32,
+    pub line_width: f32,
+    pub render_stride: u32,
+    pub size_start: f32,
+    pub size_end: f32,
+    /// Non-zero to draw through the back to front sorted indices
+    pub sorted: u32,
+}
+
+impl RenderUniforms {
+    /// Color `render.wgsl` gives a particle before premultiplying, computed on
+    /// the CPU to check the gradient and fade math without a GPU. Culled
+    /// particles are transparent black. Must follow `particle_vertex`.
+    pub fn particle_color(&self, particle: &Particle) -> glam::Vec4 {
+        if particle.age >= particle.lifetime {
+            return glam::Vec4::ZERO;
+        }
+
+        let t = if particle.lifetime.is_finite() {
+            particle.age / particle.lifetime
+        } else {
+            0.0
+        };
+        let rgb = glam::Vec4::from(self.color_start)
+            .truncate()
+            .lerp(glam::Vec4::from(self.color_end).truncate(), t);
+        let alpha = self.alpha_start + (self.alpha_end - self.alpha_start) * t;
+        let color = rgb.extend(alpha) * glam::Vec4::from(particle.color);
+
+        if self.mode == RenderMode::VelocityDebug as u32 {
+            let direction = glam::Vec4::from(particle.velocity)
+                .truncate()
+                .normalize_or_zero();
+            return (direction * 0.5 + 0.5).extend(color.w);
+        }
+
+        if self.mode == RenderMode::Blackbody as u32 {
+            return blackbody(particle.temperature).extend(color.w);
+        }
+
+        color
+    }
+}
+
+/// Factor velocities are scaled by over a `delta_time` step of `drag`, which
+/// compounds to `exp(-drag)` per second whatever the step. Mirrors
+/// `drag_factor` in `update.wgsl`.
+pub fn drag_factor(drag: f32, delta_time: f32) -> f32 {
+    (-drag * delta_time).exp()
+}
+
+/// Whole particles emitted at `rate` per second over `delta_time`, carrying the
+/// fractional part over in `accumulator` so low rates still emit
+fn accumulate_emission(accumulator: &mut f32, rate: f32, delta_time: f32) -> u32 {
+    *accumulator += rate * delta_time;
+    let count = accumulator.floor();
+    *accumulator -= count;
+
+    count as u32
+}
+
+/// Tanner Helland's fit of the blackbody color, mirrors `blackbody` in `render.wgsl`
+fn blackbody(kelvin: f32) -> glam::Vec3 {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.698_73 * (t - 60.0).powf(-0.133_204_76)
+    };
+    let g = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_17 * (t - 60.0).powf(-0.075_514_85)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+
+    let glow = ((kelvin - 500.0) / 1000.0).clamp(0.0, 1.0);
+    (glam::vec3(r, g, b) / 255.0).clamp(glam::Vec3::ZERO, glam::Vec3::ONE) * glow
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RenderMode {
+    /// Color interpolated from `color_start` to `color_end` over the particle's
+    /// age, with alpha following its own range
+    #[default]
+    Gradient,
+    /// Color is the normalized velocity direction mapped from [-1, 1] to [0, 1]
+    VelocityDebug,
+    /// Color of a black body at the particle's temperature, fading to black
+    /// as it cools below 1500 K
+    Blackbody,
+}
+
+/// How particles combine with what is drawn behind them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Colors add up whatever the order, for fire and sparks
+    #[default]
+    Additive,
+    /// Particles cover what is behind them, for smoke. Live particles are
+    /// sorted back to front on the GPU every frame.
+    AlphaBlend,
 }
 
-#[allow(unused)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ParticleEmissionMode {
     Burst(u32),
     Continuous(u32),
+    /// Emits `count` particles at once every `interval` seconds
+    PulsedBurst {
+        count: u32,
+        interval: f32,
+    },
+}
+
+impl ParticleEmissionMode {
+    /// Interpolates the rate of two modes of the same kind, different kinds
+    /// switch over halfway. Bursts only take effect on restart.
+    fn lerp(self, other: Self, t: f32) -> Self {
+        let mix = |a: u32, b: u32| (a as f32 + (b as f32 - a as f32) * t).round() as u32;
+
+        match (self, other) {
+            (ParticleEmissionMode::Continuous(rate), ParticleEmissionMode::Continuous(other)) => {
+                ParticleEmissionMode::Continuous(mix(rate, other))
+            }
+            (
+                ParticleEmissionMode::PulsedBurst { count, interval },
+                ParticleEmissionMode::PulsedBurst {
+                    count: other_count,
+                    interval: other_interval,
+                },
+            ) => ParticleEmissionMode::PulsedBurst {
+                count: mix(count, other_count),
+                interval: interval + (other_interval - interval) * t,
+            },
+            _ if t < 0.5 => self,
+            _ => other,
+        }
+    }
+}
+
+/// A point pulling particles towards it with a force of `mass / r²`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Attractor {
+    pub position: [f32; 4],
+    pub mass: f32,
+    pub padding: [f32; 3],
+}
+
+impl Attractor {
+    pub fn new(position: glam::Vec3, mass: f32) -> Self {
+        Self {
+            position: position.extend(1.0).to_array(),
+            mass,
+            padding: [0.0; 3],
+        }
+    }
+}
+
+/// Invocations dispatched by the last frame and how many of them did work
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DispatchStats {
+    pub emit_threads: u32,
+    pub emit_active: u32,
+    pub update_threads: u32,
+    pub update_active: u32,
+}
+
+/// An occupied cell of the repulsion grid
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GridCell {
+    /// Integer cell coordinates, the cell spans `cell * radius` to
+    /// `(cell + 1) * radius`
+    pub cell: glam::IVec3,
+    pub count: u32,
+}
+
+/// Uniforms assembled for a single frame, as recorded for replay
+#[repr(C, align(16))]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FrameRecord {
+    pub update: UpdateUniforms,
+    pub emit: EmitUniforms,
+    pub render: RenderUniforms,
+    pub frame: u32,
+    pub flags: u32,
+    /// Update passes run with `update`, zero in records from before fixed
+    /// timesteps counts as one
+    pub steps: u32,
+    pub padding: u32,
+}
+
+impl FrameRecord {
+    /// The simulation was stepped with `update`
+    pub const UPDATED: u32 = 1 << 0;
+    /// Particles were emitted with `emit`
+    pub const EMITTED: u32 = 1 << 1;
+
+    /// Writes `frames` to `path` as raw records in native byte order
+    pub fn save(path: &Path, frames: &[FrameRecord]) -> io::Result<()> {
+        std::fs::write(path, bytemuck::cast_slice(frames))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Vec<FrameRecord>> {
+        let data = std::fs::read(path)?;
+
+        if data.len() % std::mem::size_of::<FrameRecord>() != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "file is not a whole number of frame records",
+            ));
+        }
+
+        Ok(bytemuck::pod_collect_to_vec(&data))
+    }
+}
+
+enum ReplayMode {
+    Off,
+    Recording(Vec<FrameRecord>),
+    Replaying {
+        frames: Vec<FrameRecord>,
+        next: usize,
+    },
+}
+
+#[derive(Debug)]
+pub enum ReadbackError {
+    MapFailed,
+    PollFailed,
+}
+
+impl std::fmt::Display for ReadbackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadbackError::MapFailed => write!(f, "Failed to map readback buffer"),
+            ReadbackError::PollFailed => write!(f, "Failed to wait for the device"),
+        }
+    }
+}
+
+impl std::error::Error for ReadbackError {}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Decoding(png::DecodingError),
+    /// The image is larger than the device's 2D texture limit
+    TooLarge {
+        width: u32,
+        height: u32,
+    },
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Decoding(e) => write!(f, "Failed to decode PNG: {e}"),
+            TextureError::TooLarge { width, height } => {
+                write!(
+                    f,
+                    "A {width}x{height} texture is larger than the device supports"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreError {
+    /// The snapshot holds more particles than the system has room for
+    CapacityExceeded { count: u32, capacity: u32 },
+}
+
+impl std::fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestoreError::CapacityExceeded { count, capacity } => write!(
+                f,
+                "Snapshot holds {count} particles but the system only has room for {capacity}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RestoreError {}
+
+/// Live particles captured by `ParticleSystem::snapshot`
+#[derive(Clone, Debug, Default)]
+pub struct ParticleSnapshot {
+    pub particles: Vec<Particle>,
+    /// The same slots in the second buffer, which ghosting draws from
+    pub previous: Vec<Particle>,
+}
+
+impl ParticleSnapshot {
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+}
+
+// Settings `lerp_to_config` interpolates, as they were when the transition began
+#[derive(Clone, Copy, Debug)]
+struct ConfigOrigin {
+    position: glam::Vec3,
+    shape: ParticleEmissionShape,
+    mode: ParticleEmissionMode,
+    lifetime: f32,
+    absorb_radius: f32,
+    repulsion: (f32, f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CountReadbackState {
+    Idle,
+    Copied,
+    Mapping,
+}
+
+/// Live particle count read back from the GPU a few frames late, with the
+/// emissions it accounts for
+#[derive(Clone, Copy, Debug, Default)]
+struct AliveCount {
+    count: u32,
+    // Emissions and particles emitted covered by `count`
+    emissions: u64,
+    emitted: u64,
+    // Same for the readback in flight
+    pending_emissions: u64,
+    pending_emitted: u64,
+    // The readback in flight started before a clear
+    stale: bool,
+}
+
+impl AliveCount {
+    /// A readback of the counter starts after `emissions` emissions totalling
+    /// `emitted` particles
+    fn start_readback(&mut self, emissions: u64, emitted: u64) {
+        self.pending_emissions = emissions;
+        self.pending_emitted = emitted;
+    }
+
+    /// The readback in flight finished, with the count unless mapping failed.
+    /// A count from before a clear is dropped.
+    fn finish_readback(&mut self, count: Option<u32>) {
+        if let Some(count) = count
+            && !self.stale
+        {
+            self.count = count;
+            self.emissions = self.pending_emissions;
+            self.emitted = self.pending_emitted;
+        }
+        self.stale = false;
+    }
+
+    /// Every particle was killed after `emissions` emissions totalling
+    /// `emitted` particles, while a readback was or wasn't in flight
+    fn clear(&mut self, emissions: u64, emitted: u64, readback_in_flight: bool) {
+        self.count = 0;
+        self.emissions = emissions;
+        self.emitted = emitted;
+        self.stale = readback_in_flight;
+    }
+}
+
+/// Wall clock that stands still while paused
+#[derive(Clone, Copy, Debug)]
+struct PausableClock {
+    start: Instant,
+    // Time spent paused since the start, and when the current pause began
+    paused_duration: Duration,
+    paused_at: Option<Instant>,
+}
+
+impl PausableClock {
+    fn new(now: Instant) -> Self {
+        Self {
+            start: now,
+            paused_duration: Duration::ZERO,
+            paused_at: None,
+        }
+    }
+
+    fn pause(&mut self, now: Instant) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(now);
+        }
+    }
+
+    fn resume(&mut self, now: Instant) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused_duration += now.saturating_duration_since(paused_at);
+        }
+    }
+
+    /// Starts over from zero at `now`, running
+    fn restart(&mut self, now: Instant) {
+        *self = Self::new(now);
+    }
+
+    fn state(&self) -> SimulationState {
+        if self.paused_at.is_some() {
+            SimulationState::Paused
+        } else {
+            SimulationState::Playing
+        }
+    }
+
+    /// Time since the start at `now`, not counting the time spent paused
+    fn elapsed(&self, now: Instant) -> Duration {
+        self.paused_at
+            .unwrap_or(now)
+            .saturating_duration_since(self.start)
+            .saturating_sub(self.paused_duration)
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -59,11 +840,46 @@ pub enum SimulationState {
     Paused,
 }
 
+/// What `ParticleSystem::restart` keeps
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RestartMode {
+    /// Kills every particle and replays the same emission pattern
+    #[default]
+    Cold,
+    /// Like `Cold` with a new random seed, so emission looks different
+    Reseed,
+    /// Keeps the live particles, so the effect restarts already populated
+    Warm,
+}
+
+#[derive(Clone, Copy, Debug)]
 pub struct ParticleSystemInfo {
     pub position: glam::Vec3,
     pub mode: ParticleEmissionMode,
     pub shape: ParticleEmissionShape,
     pub lifetime: f32,
+    /// Each particle lives `lifetime` plus or minus up to this many seconds
+    pub lifetime_variance: f32,
+    pub precision: ParticlePrecision,
+    /// Offsets particle depth when testing against a shared depth buffer, to
+    /// avoid z-fighting with coplanar scene geometry. Only applies to
+    /// particles drawn as quads or streaks, point primitives can't be biased.
+    pub depth_bias: wgpu::DepthBiasState,
+    pub blend_mode: BlendMode,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SortUniforms {
+    depth_axis: [f32; 4],
+}
+
+/// One merge step of the bitonic sort
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SortStage {
+    block: u32,
+    stride: u32,
 }
 
 pub struct ParticleSystem {
@@ -73,6 +889,17 @@ pub struct ParticleSystem {
     render_uniforms_buffer: wgpu::Buffer,
     emit_uniforms_buffer: wgpu::Buffer,
     compact_uniforms_buffer: wgpu::Buffer,
+    counts_buffer: wgpu::Buffer,
+    count_readback_buffer: wgpu::Buffer,
+    cell_heads_buffer: wgpu::Buffer,
+    particle_next_buffer: wgpu::Buffer,
+    debug_counters_buffer: wgpu::Buffer,
+    attractors_buffer: wgpu::Buffer,
+    // Depth sort, padded to a power of two
+    sort_keys_buffer: wgpu::Buffer,
+    sort_indices_buffer: wgpu::Buffer,
+    sort_uniforms_buffer: wgpu::Buffer,
+    sort_stages_buffer: wgpu::Buffer,
 
     // Pipelines
     emit_pipeline: wgpu::ComputePipeline,
@@ -81,61 +908,272 @@ pub struct ParticleSystem {
     compact_bind_group: wgpu::BindGroup,
     update_pipeline: wgpu::ComputePipeline,
     update_bind_group: wgpu::BindGroup,
+    velocity_field: wgpu::TextureView,
+    projection_texture: wgpu::TextureView,
+    projection_sampler: wgpu::Sampler,
+    bin_pipeline: wgpu::ComputePipeline,
+    bin_bind_group: wgpu::BindGroup,
+    sort_init_pipeline: wgpu::ComputePipeline,
+    sort_pipeline: wgpu::ComputePipeline,
+    sort_bind_group: wgpu::BindGroup,
     render_pipeline: wgpu::RenderPipeline,
+    round_pipeline: wgpu::RenderPipeline,
+    quad_pipeline: wgpu::RenderPipeline,
+    streak_pipeline: wgpu::RenderPipeline,
+    sprite_pipeline: wgpu::RenderPipeline,
     render_bind_group: wgpu::BindGroup,
+    sprite_texture: wgpu::TextureView,
+    sprite_sampler: wgpu::Sampler,
 
     max_particles: u32,
+    precision: ParticlePrecision,
+    target_format: wgpu::TextureFormat,
+    depth_bias: wgpu::DepthBiasState,
+    depth_test: bool,
+    sample_count: u32,
+    blend_mode: BlendMode,
     position: glam::Vec3,
+    // Emitter position at the last emission
+    previous_position: glam::Vec3,
+    position_interpolation: bool,
     emission_mode: ParticleEmissionMode,
     emission_shape: ParticleEmissionShape,
     lifetime: f32,
+    lifetime_variance: f32,
+    shell_thickness: f32,
+    age_stagger: f32,
+    emission_spin: (glam::Vec3, f32),
+    curve_speed: f32,
+    // Speed and variance replacing the shape's own velocities
+    initial_speed: Option<(f32, f32)>,
+    // Color new particles from `projection_texture`
+    projection_enabled: bool,
+    extents: glam::Vec3,
+    sampling: ParticleSampling,
+    distribution: ParticleEmissionDistribution,
+    color_timeline: Vec<(f32, [f32; 4])>,
+    color_timeline_duration: f32,
+    emission_accumulator: f32,
+    next_pulse_time: f32,
+    burst_remaining: u32,
+    burst_frames: u32,
+    pending_burst: u32,
+    emissions: u64,
+    // Mixed into the emit shader's random numbers
+    seed: u32,
+    // Particles requested by every emission so far
+    emitted_particles: u64,
+    // Rate of `fill_to_capacity`, and whether that fill is still running
+    fill_rate: Option<u32>,
+    filling: bool,
+    // Emissions and emitted particles when the fill started
+    fill_start: (u64, u64),
+
+    // Simulation
+    attractors: Vec<Attractor>,
+    repulsion_radius: f32,
+    repulsion_strength: f32,
+    absorb_radius: f32,
+    drag: f32,
+    wind: glam::Vec3,
+    // Curl noise strength and swirl size
+    turbulence: (f32, f32),
+    config_origin: Option<ConfigOrigin>,
+    wrap_bounds: Option<(glam::Vec3, glam::Vec3)>,
+    velocity_field_bounds: Option<(glam::Vec3, glam::Vec3)>,
+    velocity_field_mode: VelocityFieldMode,
+    child_count: u32,
+    child_speed: f32,
+    temperature: f32,
+    cooling_rate: f32,
+
+    // Rendering
+    view_proj: glam::Mat4,
+    camera_position: glam::Vec3,
+    // Near and far distance of automatic level of detail
+    auto_lod: Option<(f32, f32)>,
+    color_start: [f32; 4],
+    color_end: [f32; 4],
+    alpha_start: f32,
+    alpha_end: f32,
+    // Scales continuous emission and alpha
+    intensity: f32,
+    render_mode: RenderMode,
+    ghost: bool,
+    ghost_fade: f32,
+    round_points: bool,
+    // Draw `sprite_texture` on every particle
+    textured: bool,
+    point_size: f32,
+    streaks: bool,
+    line_width: f32,
+    render_stride: u32,
+    // Point size scale at birth and death
+    size_curve: (f32, f32),
+    compaction: bool,
 
-    state: SimulationState,
-    start_time: Instant,
+    // Dispatch validation
+    debug_dispatch: bool,
+    emit_threads: u32,
+    update_threads: u32,
+
+    // Non-blocking readback of the live particle count
+    count_readback_state: CountReadbackState,
+    count_readback_result: Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>,
+    alive_count: AliveCount,
+    completion_reported: bool,
+
+    simulation_enabled: bool,
+    rendering_enabled: bool,
+    clock: PausableClock,
+    fixed_timestep: Option<f32>,
+    // Frame time not yet simulated in fixed steps
+    step_accumulator: f32,
+
+    replay: ReplayMode,
+    frame_index: u32,
 }
 
 impl ParticleSystem {
     pub fn new(
         device: &wgpu::Device,
-        surface_format: wgpu::TextureFormat,
+        target_format: wgpu::TextureFormat,
         info: ParticleSystemInfo,
     ) -> Self {
-        let max_particles = match info.mode {
-            ParticleEmissionMode::Burst(count) => count,
-            ParticleEmissionMode::Continuous(rate) => rate * info.lifetime.ceil() as u32,
-        };
+        let max_particles = Self::capacity(&info);
+        let precision = info.precision;
 
-        let particles_buffers = Self::create_particle_buffers(device, max_particles);
+        let particles_buffers = Self::create_particle_buffers(device, precision, max_particles);
 
         let compact_uniforms_buffer = Self::create_compact_buffer(device);
+        let counts_buffer = Self::create_counts_buffer(device);
         let update_uniforms_buffer = Self::create_update_uniforms_buffer(device);
         let emit_uniforms_buffer = Self::create_emit_uniforms_buffer(device);
         let render_uniforms_buffer = Self::create_render_uniforms_buffer(device);
+        let count_readback_buffer = Self::create_count_readback_buffer(device);
+        let (cell_heads_buffer, particle_next_buffer) =
+            Self::create_grid_buffers(device, max_particles);
+        let debug_counters_buffer = Self::create_debug_counters_buffer(device);
+        let attractors_buffer = Self::create_attractors_buffer(device);
+        let (sort_keys_buffer, sort_indices_buffer, sort_stages_buffer) =
+            Self::create_sort_buffers(device, max_particles);
+        let sort_uniforms_buffer = Self::create_sort_uniforms_buffer(device);
+        // Never sampled until a field is set, wgpu zero-initializes it
+        let velocity_field =
+            Self::create_velocity_field(device, glam::UVec3::ONE).create_view(&Default::default());
+        // Likewise never sampled until a texture is set
+        let projection_texture =
+            Self::create_placeholder_texture(device, "Projection Placeholder Texture");
+        let projection_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Projection Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        // And so is the sprite
+        let sprite_texture = Self::create_placeholder_texture(device, "Sprite Placeholder Texture");
+        let sprite_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sprite Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
 
         let (emit_pipeline, emit_bind_group) = Self::create_emit_pipeline(
             device,
+            precision,
             &particles_buffers,
             &emit_uniforms_buffer,
             &compact_uniforms_buffer,
+            &debug_counters_buffer,
+            &projection_texture,
+            &projection_sampler,
+        );
+
+        let (compact_pipeline, compact_bind_group) = Self::create_compact_pipeline(
+            device,
+            precision,
+            &particles_buffers,
+            &compact_uniforms_buffer,
+            &counts_buffer,
         );
 
-        let (compact_pipeline, compact_bind_group) =
-            Self::create_compact_pipeline(device, &particles_buffers, &compact_uniforms_buffer);
+        let (update_pipeline, update_bind_group) = Self::create_update_pipeline(
+            device,
+            precision,
+            &particles_buffers,
+            &update_uniforms_buffer,
+            &cell_heads_buffer,
+            &particle_next_buffer,
+            &debug_counters_buffer,
+            &velocity_field,
+            &compact_uniforms_buffer,
+            &counts_buffer,
+            &attractors_buffer,
+        );
+
+        let (bin_pipeline, bin_bind_group) = Self::create_bin_pipeline(
+            device,
+            precision,
+            &particles_buffers,
+            &update_uniforms_buffer,
+            &compact_uniforms_buffer,
+            &cell_heads_buffer,
+            &particle_next_buffer,
+        );
 
-        let (update_pipeline, update_bind_group) =
-            Self::create_update_pipeline(device, &particles_buffers, &update_uniforms_buffer);
+        let (sort_init_pipeline, sort_pipeline, sort_bind_group) = Self::create_sort_pipeline(
+            device,
+            precision,
+            &particles_buffers,
+            &compact_uniforms_buffer,
+            &sort_uniforms_buffer,
+            &sort_keys_buffer,
+            &sort_indices_buffer,
+            &sort_stages_buffer,
+        );
 
-        let (render_pipeline, render_bind_group) = Self::create_render_pipeline(
+        let (
+            render_pipeline,
+            round_pipeline,
+            quad_pipeline,
+            streak_pipeline,
+            sprite_pipeline,
+            render_bind_group,
+        ) = Self::create_render_pipeline(
             device,
-            surface_format,
+            precision,
+            target_format,
+            Some(info.depth_bias),
+            1,
+            info.blend_mode,
             &particles_buffers,
             &render_uniforms_buffer,
+            &sort_indices_buffer,
+            &sprite_texture,
+            &sprite_sampler,
         );
 
         Self {
             particles_buffers,
             max_particles,
+            precision,
+            target_format,
+            depth_bias: info.depth_bias,
+            depth_test: true,
+            sample_count: 1,
+            blend_mode: info.blend_mode,
             compact_uniforms_buffer,
+            counts_buffer,
+            count_readback_buffer,
+            cell_heads_buffer,
+            particle_next_buffer,
+            debug_counters_buffer,
+            attractors_buffer,
+            sort_keys_buffer,
+            sort_indices_buffer,
+            sort_uniforms_buffer,
+            sort_stages_buffer,
             update_uniforms_buffer,
             emit_uniforms_buffer,
             render_uniforms_buffer,
@@ -145,19 +1183,184 @@ impl ParticleSystem {
             compact_bind_group,
             update_pipeline,
             update_bind_group,
+            velocity_field,
+            projection_texture,
+            projection_sampler,
+            projection_enabled: false,
+            bin_pipeline,
+            bin_bind_group,
+            sort_init_pipeline,
+            sort_pipeline,
+            sort_bind_group,
             render_pipeline,
+            round_pipeline,
+            quad_pipeline,
+            streak_pipeline,
+            sprite_pipeline,
             render_bind_group,
+            sprite_texture,
+            sprite_sampler,
             position: info.position,
+            previous_position: info.position,
+            position_interpolation: false,
             emission_mode: info.mode,
             emission_shape: info.shape,
             lifetime: info.lifetime,
-            state: SimulationState::Playing,
-            start_time: Instant::now(),
+            lifetime_variance: info.lifetime_variance.max(0.0),
+            shell_thickness: 0.0,
+            age_stagger: 0.0,
+            emission_spin: (glam::Vec3::Y, 0.0),
+            curve_speed: 0.0,
+            initial_speed: None,
+            extents: glam::Vec3::splat(8.0),
+            sampling: ParticleSampling::default(),
+            distribution: ParticleEmissionDistribution::default(),
+            color_timeline: Vec::new(),
+            color_timeline_duration: 1.0,
+            emission_accumulator: 0.0,
+            next_pulse_time: 0.0,
+            burst_remaining: Self::burst_size(info.mode),
+            burst_frames: 1,
+            pending_burst: 0,
+            emissions: 0,
+            seed: 0,
+            emitted_particles: 0,
+            fill_rate: None,
+            filling: false,
+            fill_start: (0, 0),
+            attractors: vec![Attractor::new(glam::Vec3::ZERO, GRAVITY_CENTER_MASS)],
+            repulsion_radius: 0.0,
+            repulsion_strength: 0.0,
+            absorb_radius: 0.0,
+            drag: 0.0,
+            wind: glam::Vec3::ZERO,
+            turbulence: (0.0, 1.0),
+            config_origin: None,
+            wrap_bounds: None,
+            velocity_field_bounds: None,
+            velocity_field_mode: VelocityFieldMode::default(),
+            child_count: 0,
+            child_speed: 0.0,
+            temperature: 0.0,
+            cooling_rate: 0.0,
+            view_proj: glam::Mat4::IDENTITY,
+            camera_position: glam::Vec3::ZERO,
+            auto_lod: None,
+            color_start: [1.0, 0.0, 0.0, 1.0],
+            color_end: [0.0, 0.0, 1.0, 1.0],
+            alpha_start: 0.2,
+            alpha_end: 0.2,
+            intensity: 1.0,
+            render_mode: RenderMode::default(),
+            ghost: false,
+            ghost_fade: 0.5,
+            round_points: false,
+            textured: false,
+            point_size: 1.0,
+            streaks: false,
+            line_width: 1.0,
+            render_stride: 1,
+            size_curve: (1.0, 1.0),
+            compaction: true,
+            debug_dispatch: false,
+            emit_threads: 0,
+            update_threads: 0,
+            count_readback_state: CountReadbackState::Idle,
+            count_readback_result: Arc::new(Mutex::new(None)),
+            alive_count: AliveCount::default(),
+            completion_reported: false,
+            simulation_enabled: true,
+            rendering_enabled: true,
+            clock: PausableClock::new(Instant::now()),
+            fixed_timestep: None,
+            step_accumulator: 0.0,
+            replay: ReplayMode::Off,
+            frame_index: 0,
+        }
+    }
+
+    fn capacity(info: &ParticleSystemInfo) -> u32 {
+        // Sized for the longest lived particles
+        let lifetime = info.lifetime + info.lifetime_variance.max(0.0);
+
+        match info.mode {
+            ParticleEmissionMode::Burst(count) => count,
+            ParticleEmissionMode::Continuous(rate) => rate.saturating_mul(lifetime.ceil() as u32),
+            ParticleEmissionMode::PulsedBurst { count, interval } => {
+                // Pulses overlap when particles outlive the interval
+                let pulses = (lifetime / interval.max(f32::EPSILON)).ceil().max(1.0);
+                count.saturating_mul(pulses as u32)
+            }
+        }
+    }
+
+    fn burst_size(mode: ParticleEmissionMode) -> u32 {
+        match mode {
+            ParticleEmissionMode::Burst(count) => count,
+            _ => 0,
         }
     }
 
-    fn create_particle_buffers(device: &wgpu::Device, max_particles: u32) -> [wgpu::Buffer; 2] {
-        let buffer_size = (max_particles as usize * std::mem::size_of::<Particle>()) as u64;
+    /// Size in bytes of a single particle buffer for the given configuration
+    pub fn required_buffer_size(info: &ParticleSystemInfo) -> u64 {
+        Self::capacity(info) as u64 * info.precision.particle_size()
+    }
+
+    /// GPU memory a system built from `info` will allocate, to budget before
+    /// creating it
+    pub fn estimated_gpu_memory_bytes(info: &ParticleSystemInfo) -> u64 {
+        let max_particles = Self::capacity(info) as u64;
+        let index_size = std::mem::size_of::<u32>() as u64;
+
+        let particles = 2 * Self::required_buffer_size(info);
+        let grid = GRID_CELLS * index_size + max_particles.max(1) * index_size;
+        let uniforms = (std::mem::size_of::<UpdateUniforms>()
+            + std::mem::size_of::<EmitUniforms>()
+            + std::mem::size_of::<RenderUniforms>()) as u64;
+        // The indirect args and next id, the compaction counts and the alive
+        // count readback
+        let counters = 2 * std::mem::size_of::<DrawIndirectArgs>() as u64 + 3 * index_size;
+        let debug_counters = 2 * index_size;
+        let attractors = (MAX_ATTRACTORS * std::mem::size_of::<Attractor>()) as u64;
+        // Keys and indices padded to a power of two, then one uniform per stage
+        let sort = max_particles.max(1).next_power_of_two() * 2 * index_size
+            + std::mem::size_of::<SortUniforms>() as u64
+            + Self::sort_stages(max_particles as u32).len().max(1) as u64 * SORT_STAGE_STRIDE;
+
+        particles + grid + uniforms + counters + debug_counters + attractors + sort
+    }
+
+    /// GPU memory held by the system's buffers
+    pub fn gpu_memory_bytes(&self) -> u64 {
+        [
+            &self.particles_buffers[0],
+            &self.particles_buffers[1],
+            &self.update_uniforms_buffer,
+            &self.render_uniforms_buffer,
+            &self.emit_uniforms_buffer,
+            &self.compact_uniforms_buffer,
+            &self.counts_buffer,
+            &self.count_readback_buffer,
+            &self.cell_heads_buffer,
+            &self.particle_next_buffer,
+            &self.debug_counters_buffer,
+            &self.attractors_buffer,
+            &self.sort_keys_buffer,
+            &self.sort_indices_buffer,
+            &self.sort_uniforms_buffer,
+            &self.sort_stages_buffer,
+        ]
+        .iter()
+        .map(|buffer| buffer.size())
+        .sum()
+    }
+
+    fn create_particle_buffers(
+        device: &wgpu::Device,
+        precision: ParticlePrecision,
+        max_particles: u32,
+    ) -> [wgpu::Buffer; 2] {
+        let buffer_size = max_particles as u64 * precision.particle_size();
 
         [
             device.create_buffer(&wgpu::BufferDescriptor {
@@ -165,6 +1368,7 @@ impl ParticleSystem {
                 size: buffer_size,
                 usage: wgpu::BufferUsages::STORAGE
                     | wgpu::BufferUsages::VERTEX
+                    | wgpu::BufferUsages::COPY_SRC
                     | wgpu::BufferUsages::COPY_DST,
                 mapped_at_creation: false,
             }),
@@ -173,16 +1377,19 @@ impl ParticleSystem {
                 size: buffer_size,
                 usage: wgpu::BufferUsages::STORAGE
                     | wgpu::BufferUsages::VERTEX
+                    | wgpu::BufferUsages::COPY_SRC
                     | wgpu::BufferUsages::COPY_DST,
                 mapped_at_creation: false,
             }),
         ]
     }
 
+    /// The indirect draw args, followed by the id handed to the next particle
+    /// emitted or spawned
     fn create_compact_buffer(device: &wgpu::Device) -> wgpu::Buffer {
         device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Counter Buffer"),
-            size: std::mem::size_of::<DrawIndirectArgs>() as u64,
+            size: NEXT_ID_OFFSET + std::mem::size_of::<u32>() as u64,
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST
@@ -191,6 +1398,179 @@ impl ParticleSystem {
         })
     }
 
+    /// Particle count before compaction, then after it, snapshotted so the
+    /// passes know which slots are in use while the live counter moves
+    fn create_counts_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Counts Buffer"),
+            size: 2 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_count_readback_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Count Readback Buffer"),
+            size: std::mem::size_of::<DrawIndirectArgs>() as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Per-cell list heads of the repulsion grid and the per-particle links
+    /// chaining the particles of a cell together.
+    fn create_grid_buffers(
+        device: &wgpu::Device,
+        max_particles: u32,
+    ) -> (wgpu::Buffer, wgpu::Buffer) {
+        let cell_heads_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cell Heads Buffer"),
+            size: GRID_CELLS * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let particle_next_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle Next Buffer"),
+            size: max_particles.max(1) as u64 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        (cell_heads_buffer, particle_next_buffer)
+    }
+
+    fn create_placeholder_texture(device: &wgpu::Device, label: &str) -> wgpu::TextureView {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&Default::default())
+    }
+
+    fn create_velocity_field(device: &wgpu::Device, dims: glam::UVec3) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Velocity Field Texture"),
+            size: wgpu::Extent3d {
+                width: dims.x,
+                height: dims.y,
+                depth_or_array_layers: dims.z,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    fn create_attractors_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Attractors Buffer"),
+            size: (MAX_ATTRACTORS * std::mem::size_of::<Attractor>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Merge steps of a bitonic sort over `max_particles` padded to a power
+    /// of two
+    fn sort_stages(max_particles: u32) -> Vec<SortStage> {
+        let size = max_particles.max(1).next_power_of_two();
+        let mut stages = Vec::new();
+
+        let mut block = 2;
+        while block <= size {
+            let mut stride = block / 2;
+            while stride > 0 {
+                stages.push(SortStage { block, stride });
+                stride /= 2;
+            }
+            block *= 2;
+        }
+
+        stages
+    }
+
+    /// Sort keys and indices, and the uniforms of every sort stage each at
+    /// its own dynamic offset
+    fn create_sort_buffers(
+        device: &wgpu::Device,
+        max_particles: u32,
+    ) -> (wgpu::Buffer, wgpu::Buffer, wgpu::Buffer) {
+        let size = max_particles.max(1).next_power_of_two() as u64;
+
+        let sort_keys_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sort Keys Buffer"),
+            size: size * std::mem::size_of::<f32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let sort_indices_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sort Indices Buffer"),
+            size: size * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        // A single element sorts in no stage, the binding still needs one
+        let stages = Self::sort_stages(max_particles);
+        let sort_stages_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sort Stages Buffer"),
+            size: stages.len().max(1) as u64 * SORT_STAGE_STRIDE,
+            usage: wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: true,
+        });
+
+        {
+            let mut data = sort_stages_buffer.slice(..).get_mapped_range_mut();
+            for (chunk, stage) in data
+                .chunks_exact_mut(SORT_STAGE_STRIDE as usize)
+                .zip(&stages)
+            {
+                chunk[..std::mem::size_of::<SortStage>()]
+                    .copy_from_slice(bytemuck::bytes_of(stage));
+            }
+        }
+        sort_stages_buffer.unmap();
+
+        (sort_keys_buffer, sort_indices_buffer, sort_stages_buffer)
+    }
+
+    fn create_sort_uniforms_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sort Uniforms Buffer"),
+            size: std::mem::size_of::<SortUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_debug_counters_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug Counters Buffer"),
+            size: 2 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
     fn create_update_uniforms_buffer(device: &wgpu::Device) -> wgpu::Buffer {
         device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Compute Uniform Buffer"),
@@ -218,16 +1598,38 @@ impl ParticleSystem {
         })
     }
 
+    /// Shader module with the particle layout of `precision` prepended
+    fn create_particle_shader(
+        device: &wgpu::Device,
+        precision: ParticlePrecision,
+        label: &str,
+        source: &str,
+    ) -> wgpu::ShaderModule {
+        let source = format!("{}\n{source}", precision.shader_prelude());
+
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn create_emit_pipeline(
         device: &wgpu::Device,
+        precision: ParticlePrecision,
         particles_buffers: &[wgpu::Buffer; 2],
         emit_uniforms_buffer: &wgpu::Buffer,
         compact_buffer: &wgpu::Buffer,
+        debug_counters_buffer: &wgpu::Buffer,
+        projection_texture: &wgpu::TextureView,
+        projection_sampler: &wgpu::Sampler,
     ) -> (wgpu::ComputePipeline, wgpu::BindGroup) {
-        let emit_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Emit Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/emit.wgsl").into()),
-        });
+        let emit_shader = Self::create_particle_shader(
+            device,
+            precision,
+            "Emit Shader",
+            include_str!("../shaders/emit.wgsl"),
+        );
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Emit Bind Group Layout"),
@@ -262,6 +1664,32 @@ impl ParticleSystem {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
             ],
         });
 
@@ -281,6 +1709,18 @@ impl ParticleSystem {
                     binding: 2,
                     resource: compact_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: debug_counters_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(projection_texture),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(projection_sampler),
+                },
             ],
         });
 
@@ -304,13 +1744,17 @@ impl ParticleSystem {
 
     fn create_compact_pipeline(
         device: &wgpu::Device,
+        precision: ParticlePrecision,
         particles_buffers: &[wgpu::Buffer; 2],
         compact_uniforms_buffer: &wgpu::Buffer,
+        counts_buffer: &wgpu::Buffer,
     ) -> (wgpu::ComputePipeline, wgpu::BindGroup) {
-        let compact_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Compact Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/compact.wgsl").into()),
-        });
+        let compact_shader = Self::create_particle_shader(
+            device,
+            precision,
+            "Compact Shader",
+            include_str!("../shaders/compact.wgsl"),
+        );
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Compact Bind Group Layout"),
@@ -345,6 +1789,16 @@ impl ParticleSystem {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -364,6 +1818,10 @@ impl ParticleSystem {
                     binding: 2,
                     resource: compact_uniforms_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: counts_buffer.as_entire_binding(),
+                },
             ],
         });
 
@@ -385,15 +1843,26 @@ impl ParticleSystem {
         (pipeline, bind_group)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn create_update_pipeline(
         device: &wgpu::Device,
+        precision: ParticlePrecision,
         particles_buffers: &[wgpu::Buffer; 2],
         update_uniforms_buffer: &wgpu::Buffer,
+        cell_heads_buffer: &wgpu::Buffer,
+        particle_next_buffer: &wgpu::Buffer,
+        debug_counters_buffer: &wgpu::Buffer,
+        velocity_field: &wgpu::TextureView,
+        compact_uniforms_buffer: &wgpu::Buffer,
+        counts_buffer: &wgpu::Buffer,
+        attractors_buffer: &wgpu::Buffer,
     ) -> (wgpu::ComputePipeline, wgpu::BindGroup) {
-        let update_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Compute Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/update.wgsl").into()),
-        });
+        let update_shader = Self::create_particle_shader(
+            device,
+            precision,
+            "Compute Shader",
+            include_str!("../shaders/update.wgsl"),
+        );
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Compute Bind Group Layout"),
@@ -428,6 +1897,76 @@ impl ParticleSystem {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -447,6 +1986,34 @@ impl ParticleSystem {
                     binding: 2,
                     resource: particles_buffers[0].as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: cell_heads_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: particle_next_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: debug_counters_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(velocity_field),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: compact_uniforms_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: counts_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: attractors_buffer.as_entire_binding(),
+                },
             ],
         });
 
@@ -468,23 +2035,28 @@ impl ParticleSystem {
         (pipeline, bind_group)
     }
 
-    fn create_render_pipeline(
+    fn create_bin_pipeline(
         device: &wgpu::Device,
-        surface_format: wgpu::TextureFormat,
+        precision: ParticlePrecision,
         particles_buffers: &[wgpu::Buffer; 2],
-        render_uniforms_buffer: &wgpu::Buffer,
-    ) -> (wgpu::RenderPipeline, wgpu::BindGroup) {
-        let render_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Render Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/render.wgsl").into()),
-        });
+        update_uniforms_buffer: &wgpu::Buffer,
+        compact_uniforms_buffer: &wgpu::Buffer,
+        cell_heads_buffer: &wgpu::Buffer,
+        particle_next_buffer: &wgpu::Buffer,
+    ) -> (wgpu::ComputePipeline, wgpu::BindGroup) {
+        let bin_shader = Self::create_particle_shader(
+            device,
+            precision,
+            "Bin Shader",
+            include_str!("../shaders/bin.wgsl"),
+        );
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Render Bind Group Layout"),
+            label: Some("Bin Bind Group Layout"),
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -494,7 +2066,17 @@ impl ParticleSystem {
                 },
                 wgpu::BindGroupLayoutEntry {
                     binding: 1,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Storage { read_only: true },
                         has_dynamic_offset: false,
@@ -502,253 +2084,2542 @@ impl ParticleSystem {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Render Bind Group"),
+            label: Some("Bin Bind Group"),
             layout: &bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: render_uniforms_buffer.as_entire_binding(),
+                    resource: update_uniforms_buffer.as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: particles_buffers[0].as_entire_binding(),
+                    resource: particles_buffers[1].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: compact_uniforms_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: cell_heads_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: particle_next_buffer.as_entire_binding(),
                 },
             ],
         });
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
+            label: Some("Bin Pipeline Layout"),
             bind_group_layouts: &[&bind_group_layout],
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Bin Pipeline"),
             layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &render_shader,
-                entry_point: Some("vs_main"),
-                buffers: &[],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &render_shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::SrcAlpha,
-                            dst_factor: wgpu::BlendFactor::One,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                        alpha: wgpu::BlendComponent::OVER,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: Default::default(),
+            module: &bin_shader,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        (pipeline, bind_group)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_sort_pipeline(
+        device: &wgpu::Device,
+        precision: ParticlePrecision,
+        particles_buffers: &[wgpu::Buffer; 2],
+        compact_uniforms_buffer: &wgpu::Buffer,
+        sort_uniforms_buffer: &wgpu::Buffer,
+        sort_keys_buffer: &wgpu::Buffer,
+        sort_indices_buffer: &wgpu::Buffer,
+        sort_stages_buffer: &wgpu::Buffer,
+    ) -> (
+        wgpu::ComputePipeline,
+        wgpu::ComputePipeline,
+        wgpu::BindGroup,
+    ) {
+        let sort_shader = Self::create_particle_shader(
+            device,
+            precision,
+            "Sort Shader",
+            include_str!("../shaders/sort.wgsl"),
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sort Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<SortStage>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sort Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: sort_uniforms_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: particles_buffers[0].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: compact_uniforms_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: sort_keys_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: sort_indices_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: sort_stages_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<SortStage>() as u64),
+                    }),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sort Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Sort Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &sort_shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        (
+            create_pipeline("init"),
+            create_pipeline("sort_step"),
+            bind_group,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        precision: ParticlePrecision,
+        target_format: wgpu::TextureFormat,
+        depth: Option<wgpu::DepthBiasState>,
+        sample_count: u32,
+        blend_mode: BlendMode,
+        particles_buffers: &[wgpu::Buffer; 2],
+        render_uniforms_buffer: &wgpu::Buffer,
+        sort_indices_buffer: &wgpu::Buffer,
+        sprite_texture: &wgpu::TextureView,
+        sprite_sampler: &wgpu::Sampler,
+    ) -> (
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+        wgpu::BindGroup,
+    ) {
+        let render_shader = Self::create_particle_shader(
+            device,
+            precision,
+            "Render Shader",
+            include_str!("../shaders/render.wgsl"),
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Render Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Render Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: render_uniforms_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: particles_buffers[0].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: particles_buffers[1].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: sort_indices_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(sprite_texture),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(sprite_sampler),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = Self::build_render_pipeline(
+            device,
+            &pipeline_layout,
+            &render_shader,
+            target_format,
+            depth,
+            sample_count,
+            blend_mode,
+            ("vs_main", "fs_main"),
+            wgpu::PrimitiveTopology::PointList,
+        );
+
+        let round_pipeline = Self::build_render_pipeline(
+            device,
+            &pipeline_layout,
+            &render_shader,
+            target_format,
+            depth,
+            sample_count,
+            blend_mode,
+            ("vs_round", "fs_round"),
+            wgpu::PrimitiveTopology::TriangleList,
+        );
+
+        // Square points larger than the backend draws
+        let quad_pipeline = Self::build_render_pipeline(
+            device,
+            &pipeline_layout,
+            &render_shader,
+            target_format,
+            depth,
+            sample_count,
+            blend_mode,
+            ("vs_round", "fs_main"),
+            wgpu::PrimitiveTopology::TriangleList,
+        );
+
+        let streak_pipeline = Self::build_render_pipeline(
+            device,
+            &pipeline_layout,
+            &render_shader,
+            target_format,
+            depth,
+            sample_count,
+            blend_mode,
+            ("vs_streak", "fs_streak"),
+            wgpu::PrimitiveTopology::TriangleList,
+        );
+
+        let sprite_pipeline = Self::build_render_pipeline(
+            device,
+            &pipeline_layout,
+            &render_shader,
+            target_format,
+            depth,
+            sample_count,
+            blend_mode,
+            ("vs_round", "fs_sprite"),
+            wgpu::PrimitiveTopology::TriangleList,
+        );
+
+        (
+            render_pipeline,
+            round_pipeline,
+            quad_pipeline,
+            streak_pipeline,
+            sprite_pipeline,
+            bind_group,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build_render_pipeline(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        render_shader: &wgpu::ShaderModule,
+        target_format: wgpu::TextureFormat,
+        depth: Option<wgpu::DepthBiasState>,
+        sample_count: u32,
+        blend_mode: BlendMode,
+        (vertex_entry, fragment_entry): (&str, &str),
+        topology: wgpu::PrimitiveTopology,
+    ) -> wgpu::RenderPipeline {
+        // WebGPU rejects depth bias on anything but triangles
+        let depth_stencil = depth.map(|depth_bias| wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: match topology {
+                wgpu::PrimitiveTopology::TriangleList | wgpu::PrimitiveTopology::TriangleStrip => {
+                    depth_bias
+                }
+                _ => wgpu::DepthBiasState::default(),
+            },
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: render_shader,
+                entry_point: Some(vertex_entry),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: render_shader,
+                entry_point: Some(fragment_entry),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    // The fragment shader outputs premultiplied alpha
+                    blend: Some(match blend_mode {
+                        BlendMode::Additive => wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent::OVER,
+                        },
+                        BlendMode::AlphaBlend => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
             }),
             primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::PointList,
+                topology,
+                ..Default::default()
+            },
+            depth_stencil,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
                 ..Default::default()
             },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: false,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState::default(),
             multiview: None,
             cache: None,
-        });
+        })
+    }
+
+    fn update_particles(&mut self, context: &mut RenderContext) {
+        let mut pass = context
+            .encoder_mut()
+            .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Update Pass"),
+                timestamp_writes: None,
+            });
+
+        let workgroups = self.max_particles.div_ceil(256);
+        self.update_threads = workgroups * 256;
+
+        pass.set_pipeline(&self.update_pipeline);
+        pass.set_bind_group(0, &self.update_bind_group, &[]);
+        pass.dispatch_workgroups(workgroups, 1, 1);
+
+        drop(pass);
+    }
+
+    fn bin_particles(&mut self, context: &mut RenderContext) {
+        context
+            .encoder_mut()
+            .clear_buffer(&self.cell_heads_buffer, 0, None);
+
+        let mut pass = context
+            .encoder_mut()
+            .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Bin Pass"),
+                timestamp_writes: None,
+            });
+
+        pass.set_pipeline(&self.bin_pipeline);
+        pass.set_bind_group(0, &self.bin_bind_group, &[]);
+        pass.dispatch_workgroups(self.max_particles.div_ceil(256), 1, 1);
+
+        drop(pass);
+    }
+
+    fn compact_particles(&mut self, context: &mut RenderContext) {
+        // Only the slots filled last frame hold particles, then restart the count
+        let encoder = context.encoder_mut();
+        encoder.copy_buffer_to_buffer(&self.compact_uniforms_buffer, 4, &self.counts_buffer, 0, 4);
+        encoder.clear_buffer(&self.compact_uniforms_buffer, 4, Some(4));
+
+        let mut pass = context
+            .encoder_mut()
+            .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compact Pass"),
+                timestamp_writes: None,
+            });
+
+        pass.set_pipeline(&self.compact_pipeline);
+        pass.set_bind_group(0, &self.compact_bind_group, &[]);
+        pass.dispatch_workgroups(self.max_particles.div_ceil(256), 1, 1);
+
+        drop(pass);
+    }
+
+    fn emission_count(&mut self, delta_time: f32) -> u32 {
+        if self.filling
+            && let Some(rate) = self.fill_rate
+        {
+            return self.fill_count(rate, delta_time);
+        }
+
+        match self.emission_mode {
+            ParticleEmissionMode::Continuous(rate) => {
+                let rate = rate as f32 * self.intensity;
+                accumulate_emission(&mut self.emission_accumulator, rate, delta_time)
+                    .min(self.max_particles)
+            }
+            ParticleEmissionMode::Burst(size) => {
+                let count = size
+                    .div_ceil(self.burst_frames)
+                    .min(self.burst_remaining)
+                    .min(self.max_particles);
+                self.burst_remaining -= count;
+
+                count
+            }
+            ParticleEmissionMode::PulsedBurst { count, interval } => {
+                let elapsed_time = self.elapsed_time();
+                if elapsed_time < self.next_pulse_time {
+                    return 0;
+                }
+
+                // Pulses missed during a long frame are dropped, not fired together
+                let interval = interval.max(f32::EPSILON);
+                let missed = ((elapsed_time - self.next_pulse_time) / interval).floor();
+                self.next_pulse_time += (missed + 1.0) * interval;
+
+                count.min(self.max_particles)
+            }
+        }
+    }
+
+    // Continuous emission throttled to the room left, counting the particles
+    // emitted since the live count was last read back as already alive
+    fn fill_count(&mut self, rate: u32, delta_time: f32) -> u32 {
+        // Counts read back before the fill started may predate a restart
+        let (start_emissions, start_emitted) = self.fill_start;
+        let (alive, counted) = if self.alive_count.emissions > start_emissions {
+            (self.alive_count.count as u64, self.alive_count.emitted)
+        } else {
+            (0, start_emitted)
+        };
+
+        let in_flight = self.emitted_particles - counted;
+        let room = (self.max_particles as u64).saturating_sub(alive + in_flight);
+        if room == 0 {
+            self.filling = false;
+            return 0;
+        }
+
+        let count = accumulate_emission(&mut self.emission_accumulator, rate as f32, delta_time);
+
+        (count as u64).min(room) as u32
+    }
+
+    fn emit_uniforms(&mut self, delta_time: f32) -> Option<EmitUniforms> {
+        let count = self
+            .emission_count(delta_time)
+            .saturating_add(std::mem::take(&mut self.pending_burst))
+            .min(self.max_particles);
+
+        if count == 0 {
+            return None;
+        }
+
+        let (distribution, sigma) = self.distribution.to_uniforms();
+
+        let (line_start, line_end) = match self.emission_shape {
+            ParticleEmissionShape::Line { start, end } => (start, end),
+            _ => (glam::Vec3::ZERO, glam::Vec3::ZERO),
+        };
+
+        let phase = self.curve_speed * self.elapsed_time() * std::f32::consts::TAU;
+        let curve = match self.emission_shape {
+            ParticleEmissionShape::Helix {
+                radius,
+                pitch,
+                turns,
+            } => [radius, pitch, turns, phase],
+            ParticleEmissionShape::Spiral { radius, turns } => [radius, 0.0, turns, phase],
+            _ => [0.0; 4],
+        };
+
+        let cone = match self.emission_shape {
+            ParticleEmissionShape::Cone { angle, direction } => direction.extend(angle).to_array(),
+            _ => [0.0; 4],
+        };
+
+        let (disk_radii, disk_normal) = match self.emission_shape {
+            ParticleEmissionShape::Disk {
+                inner_radius,
+                outer_radius,
+                normal,
+            } => (
+                [inner_radius.min(outer_radius), outer_radius, 0.0, 0.0],
+                normal.normalize_or(glam::Vec3::Y).extend(0.0).to_array(),
+            ),
+            _ => ([0.0; 4], [0.0; 4]),
+        };
+
+        let (spin_axis, spin_rate) = self.emission_spin;
+        let orientation = glam::Quat::from_axis_angle(spin_axis, spin_rate * self.elapsed_time());
+
+        let emit_uniforms = EmitUniforms {
+            position: self.position.extend(1.0).to_array(),
+            count,
+            lifetime: self.lifetime,
+            shape: self.emission_shape.to_uniforms(),
+            elapsed_time: self.elapsed_time(),
+            sample_base: self.emitted_particles as u32,
+            shell_thickness: self.shell_thickness,
+            distribution,
+            sigma,
+            tint: self.timeline_color(),
+            line_start: line_start.extend(0.0).to_array(),
+            line_end: line_end.extend(0.0).to_array(),
+            extents: self.extents.extend(0.0).to_array(),
+            sampling: self.sampling as u32,
+            debug: self.debug_dispatch as u32,
+            interpolate: self.position_interpolation as u32,
+            age_stagger: self.age_stagger,
+            previous_position: self.previous_position.extend(1.0).to_array(),
+            orientation: orientation.to_array(),
+            curve,
+            temperature: self.temperature,
+            project_color: self.projection_enabled as u32,
+            seed: self.seed,
+            lifetime_variance: self.lifetime_variance,
+            projection_view_proj: self.view_proj.to_cols_array_2d(),
+            cone,
+            disk_radii,
+            disk_normal,
+            initial_speed: self.initial_speed.map_or(0.0, |(speed, _)| speed),
+            speed_variance: self.initial_speed.map_or(0.0, |(_, variance)| variance),
+            override_speed: self.initial_speed.is_some() as u32,
+            padding: 0,
+        };
+
+        self.previous_position = self.position;
+
+        Some(emit_uniforms)
+    }
+
+    fn emit_particles(&mut self, context: &mut RenderContext, emit_uniforms: &EmitUniforms) {
+        self.emissions += 1;
+        self.emitted_particles += emit_uniforms.count as u64;
+
+        context.queue().write_buffer(
+            &self.emit_uniforms_buffer,
+            0,
+            bytemuck::cast_slice(&[*emit_uniforms]),
+        );
+
+        let mut pass = context
+            .encoder_mut()
+            .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Emit Pass"),
+                timestamp_writes: None,
+            });
+
+        let workgroups = emit_uniforms.count.div_ceil(256);
+        self.emit_threads = workgroups * 256;
+
+        pass.set_pipeline(&self.emit_pipeline);
+        pass.set_bind_group(0, &self.emit_bind_group, &[]);
+        pass.dispatch_workgroups(workgroups, 1, 1);
+
+        drop(pass);
+    }
+
+    fn render_uniforms(&self, (width, height): (u32, u32)) -> RenderUniforms {
+        RenderUniforms {
+            view_proj: self.view_proj.to_cols_array_2d(),
+            color_start: self.color_start,
+            color_end: self.color_end,
+            mode: self.render_mode as u32,
+            ghost_fade: self.ghost_fade,
+            alpha_start: self.alpha_start * self.intensity,
+            alpha_end: self.alpha_end * self.intensity,
+            viewport_size: [width.max(1) as f32, height.max(1) as f32],
+            point_size: self.point_size,
+            line_width: self.line_width,
+            render_stride: self.render_stride.max(self.lod_stride()),
+            size_start: self.size_curve.0,
+            size_end: self.size_curve.1,
+            sorted: (self.blend_mode == BlendMode::AlphaBlend) as u32,
+        }
+    }
+
+    // Grows geometrically from 1 at the near distance to MAX_LOD_STRIDE at the
+    // far one, keeping the density on screen roughly constant
+    fn lod_stride(&self) -> u32 {
+        let Some((near, far)) = self.auto_lod else {
+            return 1;
+        };
+
+        let distance = self.camera_position.distance(self.position);
+        let t = ((distance - near) / (far - near).max(f32::EPSILON)).clamp(0.0, 1.0);
+
+        MAX_LOD_STRIDE.powf(t).round() as u32
+    }
+
+    // Orders the live particles back to front into the sort indices with a
+    // bitonic sort over the whole padded capacity
+    fn sort_particles(&self, context: &mut RenderContext, render_uniforms: &RenderUniforms) {
+        // Clip w is the view depth of a perspective projection, orthographic
+        // projections keep w at 1 and have a linear z instead
+        let view_proj = glam::Mat4::from_cols_array_2d(&render_uniforms.view_proj);
+        let depth_axis = if view_proj.row(3).truncate() == glam::Vec3::ZERO {
+            view_proj.row(2)
+        } else {
+            view_proj.row(3)
+        };
+
+        let uniforms = SortUniforms {
+            depth_axis: depth_axis.to_array(),
+        };
+        context
+            .queue()
+            .write_buffer(&self.sort_uniforms_buffer, 0, bytemuck::bytes_of(&uniforms));
+
+        let mut pass = context
+            .encoder_mut()
+            .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Sort Pass"),
+                timestamp_writes: None,
+            });
+
+        let workgroups = self.max_particles.max(1).next_power_of_two().div_ceil(256);
+
+        pass.set_pipeline(&self.sort_init_pipeline);
+        pass.set_bind_group(0, &self.sort_bind_group, &[0]);
+        pass.dispatch_workgroups(workgroups, 1, 1);
+
+        pass.set_pipeline(&self.sort_pipeline);
+        for stage in 0..Self::sort_stages(self.max_particles).len() {
+            let offset = (stage as u64 * SORT_STAGE_STRIDE) as u32;
+            pass.set_bind_group(0, &self.sort_bind_group, &[offset]);
+            pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+
+        drop(pass);
+    }
+
+    fn render_particles(&self, context: &mut RenderContext, render_uniforms: &RenderUniforms) {
+        context.queue().write_buffer(
+            &self.render_uniforms_buffer,
+            0,
+            bytemuck::cast_slice(&[*render_uniforms]),
+        );
+
+        // Each instance draws its current position, plus its previous one when
+        // ghosting. A streak covers both in a single quad.
+        let vertex_count: u32 = if self.streaks {
+            6
+        } else {
+            let vertices_per_point = if self.uses_quads() { 6 } else { 1 };
+            vertices_per_point * if self.ghost { 2 } else { 1 }
+        };
+        context.queue().write_buffer(
+            &self.compact_uniforms_buffer,
+            0,
+            bytemuck::bytes_of(&vertex_count),
+        );
+
+        let view = context.view().clone();
+        let resolve_target = context.resolve_target().cloned();
+        let depth_view = context.depth_view().cloned();
+        let (color_load, depth_load) = context.load_ops();
+        let mut pass = context
+            .encoder_mut()
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: resolve_target.as_ref(),
+                    depth_slice: None,
+                    ops: wgpu::Operations {
+                        load: color_load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: depth_view.as_ref().map(|view| {
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: depth_load,
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+        if self.streaks {
+            pass.set_pipeline(&self.streak_pipeline);
+        } else if self.textured {
+            pass.set_pipeline(&self.sprite_pipeline);
+        } else if self.round_points {
+            pass.set_pipeline(&self.round_pipeline);
+        } else if self.uses_quads() {
+            pass.set_pipeline(&self.quad_pipeline);
+        } else {
+            pass.set_pipeline(&self.render_pipeline);
+        }
+        pass.set_bind_group(0, &self.render_bind_group, &[]);
+        if self.compaction {
+            pass.draw_indirect(&self.compact_uniforms_buffer, 0);
+        } else {
+            pass.draw(0..vertex_count, 0..self.max_particles);
+        }
+    }
+
+    // Copies the counter one frame, maps it the next once that copy has been
+    // submitted, and reads it whenever the mapping completes
+    fn track_alive_count(&mut self, context: &mut RenderContext) {
+        match self.count_readback_state {
+            CountReadbackState::Idle => {
+                context.encoder_mut().copy_buffer_to_buffer(
+                    &self.compact_uniforms_buffer,
+                    0,
+                    &self.count_readback_buffer,
+                    0,
+                    std::mem::size_of::<DrawIndirectArgs>() as u64,
+                );
+                self.alive_count
+                    .start_readback(self.emissions, self.emitted_particles);
+                self.count_readback_state = CountReadbackState::Copied;
+            }
+            CountReadbackState::Copied => {
+                let result = self.count_readback_result.clone();
+                self.count_readback_buffer
+                    .slice(..)
+                    .map_async(wgpu::MapMode::Read, move |r| {
+                        if let Ok(mut result) = result.lock() {
+                            *result = Some(r);
+                        }
+                    });
+                self.count_readback_state = CountReadbackState::Mapping;
+            }
+            CountReadbackState::Mapping => {
+                let result = match self.count_readback_result.lock() {
+                    Ok(mut result) => result.take(),
+                    Err(_) => None,
+                };
+
+                match result {
+                    Some(Ok(())) => {
+                        let data = self.count_readback_buffer.slice(..).get_mapped_range();
+                        let args: DrawIndirectArgs = bytemuck::pod_read_unaligned(&data);
+                        drop(data);
+
+                        self.count_readback_buffer.unmap();
+                        self.alive_count
+                            .finish_readback(Some(args.instance_count.min(self.max_particles)));
+                        self.count_readback_state = CountReadbackState::Idle;
+                    }
+                    Some(Err(_)) => {
+                        self.alive_count.finish_readback(None);
+                        self.count_readback_state = CountReadbackState::Idle;
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    fn update_uniforms(&self, delta_time: f32) -> UpdateUniforms {
+        let (bounds_min, bounds_max, bounds_mode) = match self.wrap_bounds {
+            Some((min, max)) => (min, max, 1),
+            None => (glam::Vec3::ZERO, glam::Vec3::ZERO, 0),
+        };
+
+        let (field_min, field_max, field_mode) = match self.velocity_field_bounds {
+            Some((min, max)) => (min, max, self.velocity_field_mode as u32 + 1),
+            None => (glam::Vec3::ZERO, glam::Vec3::ZERO, 0),
+        };
+
+        UpdateUniforms {
+            elapsed_time: self.elapsed_time(),
+            delta_time,
+            repulsion_radius: self.repulsion_radius,
+            repulsion_strength: self.repulsion_strength,
+            bounds_min: bounds_min.extend(0.0).to_array(),
+            bounds_max: bounds_max.extend(0.0).to_array(),
+            bounds_mode,
+            debug: self.debug_dispatch as u32,
+            field_mode,
+            child_count: self.child_count,
+            field_min: field_min.extend(0.0).to_array(),
+            field_max: field_max.extend(0.0).to_array(),
+            child_speed: self.child_speed,
+            cooling_rate: self.cooling_rate,
+            absorb_radius: self.absorb_radius,
+            attractor_count: self.attractors.len() as u32,
+            drag: self.drag,
+            turbulence_strength: self.turbulence.0,
+            turbulence_scale: self.turbulence.1,
+            padding: 0,
+            wind: self.wind.extend(0.0).to_array(),
+        }
+    }
+
+    fn next_replayed_frame(&mut self) -> Option<FrameRecord> {
+        let ReplayMode::Replaying { frames, next } = &mut self.replay else {
+            return None;
+        };
+
+        let frame = frames.get(*next).copied();
+        *next += 1;
+
+        if frame.is_none() {
+            self.replay = ReplayMode::Off;
+        }
+
+        frame
+    }
+
+    /// Update passes to run this frame and the time each one covers, a single
+    /// pass over the whole frame without a fixed timestep
+    fn timesteps(&mut self, delta_time: f32) -> (u32, f32) {
+        let Some(step) = self.fixed_timestep else {
+            return (1, delta_time);
+        };
+
+        self.step_accumulator += delta_time;
+        let steps = (self.step_accumulator / step).min(MAX_FIXED_STEPS as f32) as u32;
+        self.step_accumulator = (self.step_accumulator - steps as f32 * step).min(step);
+
+        (steps, step)
+    }
+
+    /// Records the frame's simulation and rendering into `context`. Several
+    /// systems can share one context, they are then submitted together when
+    /// the frame ends.
+    pub fn update(&mut self, context: &mut RenderContext, delta_time: f32) {
+        let replayed = self.next_replayed_frame();
+
+        let mut record = FrameRecord {
+            frame: self.frame_index,
+            ..bytemuck::Zeroable::zeroed()
+        };
+
+        self.emit_threads = 0;
+        self.update_threads = 0;
+        if self.debug_dispatch {
+            context
+                .encoder_mut()
+                .clear_buffer(&self.debug_counters_buffer, 0, None);
+        }
+
+        let simulate = match &replayed {
+            Some(frame) => frame.flags & FrameRecord::UPDATED != 0,
+            None => !self.is_paused() && self.simulation_enabled,
+        };
+
+        let (steps, step_time) = match &replayed {
+            _ if !simulate => (0, 0.0),
+            Some(frame) => (frame.steps.max(1), frame.update.delta_time),
+            None => self.timesteps(delta_time),
+        };
+
+        if steps > 0 {
+            let update_uniforms = match &replayed {
+                Some(frame) => frame.update,
+                None => self.update_uniforms(step_time),
+            };
+            context.queue().write_buffer(
+                &self.update_uniforms_buffer,
+                0,
+                bytemuck::cast_slice(&[update_uniforms]),
+            );
+            record.update = update_uniforms;
+            record.flags |= FrameRecord::UPDATED;
+            record.steps = steps;
+
+            if !self.attractors.is_empty() {
+                context.queue().write_buffer(
+                    &self.attractors_buffer,
+                    0,
+                    bytemuck::cast_slice(&self.attractors),
+                );
+            }
+
+            for _ in 0..steps {
+                if self.compaction {
+                    self.compact_particles(context);
+                } else {
+                    // Particles keep their slots, the update pass still reads from buffer 1
+                    let size = self.max_particles as u64 * self.precision.particle_size();
+                    context.encoder_mut().copy_buffer_to_buffer(
+                        &self.particles_buffers[0],
+                        0,
+                        &self.particles_buffers[1],
+                        0,
+                        size,
+                    );
+                }
+                // Children appended by the update pass land past this count
+                context.encoder_mut().copy_buffer_to_buffer(
+                    &self.compact_uniforms_buffer,
+                    4,
+                    &self.counts_buffer,
+                    4,
+                    4,
+                );
+                if update_uniforms.repulsion_radius > 0.0 {
+                    self.bin_particles(context);
+                }
+                self.update_particles(context);
+            }
+
+            // Emission covers all the steps at once, the uniforms are only
+            // written once per frame
+            let emit_uniforms = match &replayed {
+                Some(frame) => (frame.flags & FrameRecord::EMITTED != 0).then_some(frame.emit),
+                None => self.emit_uniforms(steps as f32 * step_time),
+            };
+            if let Some(emit_uniforms) = emit_uniforms {
+                self.emit_particles(context, &emit_uniforms);
+                record.emit = emit_uniforms;
+                record.flags |= FrameRecord::EMITTED;
+            }
+        }
+
+        let render_uniforms = match &replayed {
+            Some(frame) => frame.render,
+            None => self.render_uniforms(context.size()),
+        };
+        if self.rendering_enabled {
+            if render_uniforms.sorted != 0 {
+                self.sort_particles(context, &render_uniforms);
+            }
+            self.render_particles(context, &render_uniforms);
+        }
+        record.render = render_uniforms;
+
+        self.track_alive_count(context);
+
+        if let ReplayMode::Recording(frames) = &mut self.replay {
+            frames.push(record);
+        }
+        self.frame_index = self.frame_index.wrapping_add(1);
+    }
+
+    /// Starts recording the uniforms of every following frame, dropping any
+    /// previous recording.
+    pub fn start_recording(&mut self) {
+        self.replay = ReplayMode::Recording(Vec::new());
+    }
+
+    /// Stops recording and returns the frames recorded so far
+    pub fn take_recording(&mut self) -> Vec<FrameRecord> {
+        match std::mem::replace(&mut self.replay, ReplayMode::Off) {
+            ReplayMode::Recording(frames) => frames,
+            replay => {
+                self.replay = replay;
+                Vec::new()
+            }
+        }
+    }
+
+    /// Feeds `frames` back one per `update` instead of assembling the uniforms
+    /// from the current state, until they run out. Restart the system first to
+    /// reproduce a recording from its beginning.
+    pub fn replay(&mut self, frames: Vec<FrameRecord>) {
+        self.replay = ReplayMode::Replaying { frames, next: 0 };
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        matches!(self.replay, ReplayMode::Replaying { .. })
+    }
+
+    /// Number of live particles as of a few frames ago, read back without
+    /// stalling the GPU. `read_alive_count` gives the current one.
+    pub fn alive_count(&self) -> u32 {
+        self.alive_count.count
+    }
+
+    /// True once a burst system has emitted everything and its last particle
+    /// has died. Continuous and pulsed systems never finish.
+    pub fn is_finished(&self) -> bool {
+        !self.filling
+            && matches!(self.emission_mode, ParticleEmissionMode::Burst(_))
+            && self.burst_remaining == 0
+            && self.alive_count.emissions == self.emissions
+            && self.alive_count.count == 0
+    }
+
+    pub fn set_position(&mut self, position: glam::Vec3) {
+        self.position = position;
+    }
+
+    /// Spreads each emission along the straight path the emitter moved since
+    /// the previous one, so a fast moving emitter leaves a continuous trail.
+    pub fn set_position_interpolation(&mut self, enabled: bool) {
+        self.position_interpolation = enabled;
+    }
+
+    /// Spreads the initial burst evenly over `frames` frames instead of
+    /// emitting it all in the first one, smoothing the startup of large bursts.
+    pub fn set_burst_frames(&mut self, frames: u32) {
+        self.burst_frames = frames.max(1);
+    }
+
+    /// Emits `count` extra particles on the next update, on top of the regular
+    /// emission. Particles that don't fit in the remaining capacity are dropped.
+    pub fn burst(&mut self, count: u32) {
+        self.pending_burst = self.pending_burst.saturating_add(count);
+    }
+
+    /// Changes the particles per second of continuous emission, taking effect
+    /// on the next update. Ignored in the other modes. A single frame never
+    /// emits more than `max_particles`, however high the rate.
+    pub fn set_emission_rate(&mut self, rate: u32) {
+        if let ParticleEmissionMode::Continuous(current) = &mut self.emission_mode {
+            *current = rate;
+        }
+    }
+
+    /// Particles per second of continuous emission, `None` in the other modes
+    pub fn emission_rate(&self) -> Option<u32> {
+        match self.emission_mode {
+            ParticleEmissionMode::Continuous(rate) => Some(rate),
+            _ => None,
+        }
+    }
+
+    /// Replaces the emission mode with emitting `rate` particles per second
+    /// until the live count reaches `max_particles`, then emits nothing. A
+    /// restart fills the system again.
+    pub fn fill_to_capacity(&mut self, rate: u32) {
+        self.emission_mode = ParticleEmissionMode::Burst(0);
+        self.burst_remaining = 0;
+        self.emission_accumulator = 0.0;
+        self.fill_rate = Some(rate);
+        self.start_fill();
+    }
+
+    fn start_fill(&mut self) {
+        self.filling = self.fill_rate.is_some();
+        self.fill_start = (self.emissions, self.emitted_particles);
+    }
+
+    /// Returns true exactly once after the system finishes, to be polled every
+    /// frame to chain effects. Re-armed by `restart`.
+    pub fn took_completion_event(&mut self) -> bool {
+        if self.completion_reported || !self.is_finished() {
+            return false;
+        }
+
+        self.completion_reported = true;
+        true
+    }
+
+    /// Tints newly emitted particles with a color that evolves over the life of
+    /// the whole system. `points` are `(t, color)` pairs with `t` in [0, 1]
+    /// relative to `duration` seconds, sorted by `t`.
+    pub fn set_system_color_timeline(&mut self, duration: f32, points: &[(f32, [f32; 4])]) {
+        self.color_timeline = points.to_vec();
+        self.color_timeline_duration = duration.max(f32::EPSILON);
+    }
+
+    fn timeline_color(&self) -> [f32; 4] {
+        let t = (self.elapsed_time() / self.color_timeline_duration).clamp(0.0, 1.0);
+
+        let (first, last) = match (self.color_timeline.first(), self.color_timeline.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return [1.0; 4],
+        };
+
+        if t <= first.0 {
+            return first.1;
+        }
+
+        for window in self.color_timeline.windows(2) {
+            let (t0, c0) = window[0];
+            let (t1, c1) = window[1];
+
+            if t <= t1 {
+                let f = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 };
+                return glam::Vec4::from(c0)
+                    .lerp(glam::Vec4::from(c1), f)
+                    .to_array();
+            }
+        }
+
+        last.1
+    }
+
+    /// Spreads sphere and cube emission over `radius ± thickness` instead of
+    /// the exact surface. For lines, particles are jittered up to `thickness`
+    /// away from the segment.
+    pub fn set_shell_thickness(&mut self, thickness: f32) {
+        self.shell_thickness = thickness.max(0.0);
+    }
+
+    pub fn set_emission_shape(&mut self, shape: ParticleEmissionShape) {
+        self.emission_shape = shape;
+    }
+
+    /// Turns per second the helix and spiral shapes advance along themselves,
+    /// so a continuous emission traces a rotating curve
+    pub fn set_curve_speed(&mut self, turns_per_second: f32) {
+        self.curve_speed = turns_per_second;
+    }
+
+    /// Launches new particles at `speed` plus or minus up to `variance`
+    /// instead of the shape's orbital velocities. They fly away from the
+    /// emitter's center, in a random direction from the center itself, and
+    /// cones keep their own directions.
+    pub fn set_initial_speed(&mut self, speed: f32, variance: f32) {
+        self.initial_speed = Some((speed.max(0.0), variance.max(0.0)));
+    }
+
+    /// Goes back to the velocities given by the emission shape
+    pub fn clear_initial_speed(&mut self) {
+        self.initial_speed = None;
+    }
+
+    pub fn initial_speed(&self) -> Option<(f32, f32)> {
+        self.initial_speed
+    }
+
+    /// Half-extents of the sphere and cube shapes along each axis, turning them
+    /// into an ellipsoid and a box.
+    pub fn set_emission_extents(&mut self, extents: glam::Vec3) {
+        self.extents = extents.max(glam::Vec3::ZERO);
+    }
+
+    /// Spreads the lifetime of new particles evenly over `lifetime` plus or
+    /// minus `variance` seconds. The buffers keep the size they were created
+    /// with, so raising it past `ParticleSystemInfo::lifetime_variance` can
+    /// fill them up and hold back emission until particles die.
+    pub fn set_lifetime_variance(&mut self, variance: f32) {
+        self.lifetime_variance = variance.max(0.0);
+    }
+
+    pub fn lifetime_variance(&self) -> f32 {
+        self.lifetime_variance
+    }
+
+    /// Gives new particles a random initial age in
+    /// `[0, lifetime * fraction)`, so they don't all reach the end of their
+    /// life together. Has no effect on infinite lifetimes.
+    pub fn set_age_stagger(&mut self, fraction: f32) {
+        self.age_stagger = fraction.clamp(0.0, 1.0);
+    }
+
+    /// Rotates the emission shape around `axis` at `rate` radians per second
+    pub fn set_emission_spin(&mut self, axis: glam::Vec3, rate: f32) {
+        self.emission_spin = (axis.try_normalize().unwrap_or(glam::Vec3::Y), rate);
+    }
+
+    /// Uploads a grid of `dims` velocities covering the box `bounds`, stored
+    /// x first then y then z. Particles inside the box sample it with trilinear
+    /// filtering, missing values are zero.
+    pub fn set_velocity_field(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &[glam::Vec3],
+        dims: glam::UVec3,
+        bounds: (glam::Vec3, glam::Vec3),
+    ) {
+        let dims = dims.max(glam::UVec3::ONE);
+
+        let mut texels = vec![[0.0f32; 4]; (dims.x * dims.y * dims.z) as usize];
+        for (texel, velocity) in texels.iter_mut().zip(data) {
+            *texel = velocity.extend(0.0).to_array();
+        }
+
+        let texture = Self::create_velocity_field(device, dims);
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(&texels),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(dims.x * 16),
+                rows_per_image: Some(dims.y),
+            },
+            texture.size(),
+        );
+
+        self.velocity_field = texture.create_view(&Default::default());
+        self.velocity_field_bounds = Some(bounds);
+        self.recreate_pipelines(device);
+    }
+
+    pub fn clear_velocity_field(&mut self) {
+        self.velocity_field_bounds = None;
+    }
+
+    /// Colors new particles with `texture` projected through the view
+    /// projection set by `set_view_proj`, as if it were shown on screen,
+    /// multiplied by the color timeline. Particles emitted outside the view
+    /// keep the timeline color. The texture must be a filterable float 2D
+    /// texture.
+    pub fn set_projection_texture(&mut self, device: &wgpu::Device, texture: wgpu::TextureView) {
+        self.projection_texture = texture;
+        self.projection_enabled = true;
+        self.recreate_pipelines(device);
+    }
+
+    pub fn clear_projection_texture(&mut self) {
+        self.projection_enabled = false;
+    }
+
+    /// Makes every emitted particle burst into `count` children flying off at
+    /// `speed` in random directions when it dies. Children don't spawn
+    /// children of their own, a count of zero turns spawning off. Children
+    /// only fit in spare capacity, see `resize_capacity`.
+    pub fn set_child_spawn(&mut self, count: u32, speed: f32) {
+        self.child_count = count;
+        self.child_speed = speed;
+    }
+
+    /// New particles start at `initial` Kelvin and lose `cooling_rate` Kelvin
+    /// per second, see `RenderMode::Blackbody`
+    pub fn set_temperature(&mut self, initial: f32, cooling_rate: f32) {
+        self.temperature = initial.max(0.0);
+        self.cooling_rate = cooling_rate;
+    }
+
+    pub fn set_velocity_field_mode(&mut self, mode: VelocityFieldMode) {
+        self.velocity_field_mode = mode;
+    }
+
+    pub fn set_sampling(&mut self, sampling: ParticleSampling) {
+        self.sampling = sampling;
+    }
+
+    /// Only affects the sphere and cube shapes
+    pub fn set_emission_distribution(&mut self, distribution: ParticleEmissionDistribution) {
+        self.distribution = distribution;
+    }
+
+    /// Skips compaction for populations where nothing ever dies, such as a
+    /// burst with infinite lifetime. All `max_particles` slots are then updated
+    /// and drawn every frame and dead particles are never recycled.
+    pub fn set_compaction(&mut self, enabled: bool) {
+        self.compaction = enabled;
+    }
+
+    pub fn set_view_proj(&mut self, view_proj: glam::Mat4) {
+        self.view_proj = view_proj;
+    }
+
+    /// Where the camera is, only used for automatic level of detail
+    pub fn set_camera_position(&mut self, position: glam::Vec3) {
+        self.camera_position = position;
+    }
+
+    /// Draws fewer particles as the camera moves away from the emitter, all
+    /// of them within `near_dist` and one in 16 past `far_dist`. A larger
+    /// `set_render_stride` still wins.
+    pub fn set_auto_lod(&mut self, enabled: bool, near_dist: f32, far_dist: f32) {
+        self.auto_lod = enabled.then_some((near_dist, far_dist.max(near_dist)));
+    }
+
+    /// Color gradient over the particle's age, from `start` at birth to `end`
+    /// at death. Particles with an infinite lifetime stay at `start`. Alpha
+    /// comes from `set_alpha_range` instead.
+    pub fn set_colors(&mut self, start: [f32; 4], end: [f32; 4]) {
+        self.color_start = start;
+        self.color_end = end;
+    }
+
+    pub fn colors(&self) -> ([f32; 4], [f32; 4]) {
+        (self.color_start, self.color_end)
+    }
+
+    /// Alpha over the particle's age, interpolated independently of the color
+    /// gradient whose own alpha is ignored.
+    pub fn set_alpha_range(&mut self, start: f32, end: f32) {
+        self.alpha_start = start.clamp(0.0, 1.0);
+        self.alpha_end = end.clamp(0.0, 1.0);
+    }
+
+    /// Master knob to fade a whole effect, scaling the continuous emission
+    /// rate and the alpha range. Bursts and fills are left as they are.
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.max(0.0);
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// Draws particles as antialiased discs instead of single pixels, as wide
+    /// as the point size. Pair it with `set_point_size` to get visible discs.
+    pub fn set_round_points(&mut self, enabled: bool) {
+        self.round_points = enabled;
+    }
+
+    /// Draws every particle as a billboard of the PNG `image_bytes`, tinted
+    /// by its color, at the point size. The image is taken as sRGB with
+    /// straight alpha. Takes precedence over round points.
+    pub fn set_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image_bytes: &[u8],
+    ) -> Result<(), TextureError> {
+        let mut decoder = png::Decoder::new(io::Cursor::new(image_bytes));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(TextureError::Decoding)?;
+
+        let mut data = vec![0; reader.output_buffer_size().unwrap_or(0)];
+        let info = reader
+            .next_frame(&mut data)
+            .map_err(TextureError::Decoding)?;
+        let (width, height) = (info.width, info.height);
+
+        let max_size = device.limits().max_texture_dimension_2d;
+        if width > max_size || height > max_size {
+            return Err(TextureError::TooLarge { width, height });
+        }
+
+        // Expanded to 8 bits per channel, only the channel count varies
+        let pixels: Vec<u8> = data[..info.buffer_size()]
+            .chunks_exact(info.color_type.samples())
+            .flat_map(|pixel| match *pixel {
+                [gray] => [gray, gray, gray, 255],
+                [gray, alpha] => [gray, gray, gray, alpha],
+                [r, g, b] => [r, g, b, 255],
+                [r, g, b, a] => [r, g, b, a],
+                _ => unreachable!(),
+            })
+            .collect();
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Sprite Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            &pixels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+
+        self.sprite_texture = texture.create_view(&Default::default());
+        self.textured = true;
+        self.recreate_render_pipelines(device);
+
+        Ok(())
+    }
+
+    /// Goes back to drawing plain points
+    pub fn clear_texture(&mut self) {
+        self.textured = false;
+    }
+
+    pub fn textured(&self) -> bool {
+        self.textured
+    }
+
+    /// Replaces the depth bias given in `ParticleSystemInfo`, rebuilding the
+    /// render pipelines
+    pub fn set_depth_bias(&mut self, device: &wgpu::Device, depth_bias: wgpu::DepthBiasState) {
+        self.depth_bias = depth_bias;
+        self.recreate_render_pipelines(device);
+    }
+
+    /// Switches between additive and alpha-over blending, the latter sorting
+    /// the particles back to front every frame before drawing them
+    pub fn set_blend_mode(&mut self, device: &wgpu::Device, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+        self.recreate_render_pipelines(device);
+    }
+
+    /// Rebuilds the render pipelines for another blend mode, keeping the
+    /// particles. Same as `set_blend_mode`.
+    pub fn rebuild_render_pipeline(&mut self, device: &wgpu::Device, blend_mode: BlendMode) {
+        self.set_blend_mode(device, blend_mode);
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    /// Tests particles against the frame's depth buffer. Must be turned off
+    /// to draw with a renderer built without one, see
+    /// `RendererBuilder::depth_buffer`.
+    pub fn set_depth_test(&mut self, device: &wgpu::Device, enabled: bool) {
+        self.depth_test = enabled;
+        self.recreate_render_pipelines(device);
+    }
+
+    /// Samples per pixel the render pipelines draw with, must match
+    /// `Renderer::sample_count`
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.sample_count = sample_count.max(1);
+        self.recreate_render_pipelines(device);
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Width of a particle in pixels, at least 1. Point primitives can't be
+    /// drawn wider than `MAX_POINT_SIZE`, which is a single pixel on every
+    /// backend, so larger points fall back to screen-aligned square quads.
+    pub fn set_point_size(&mut self, size: f32) {
+        self.point_size = size.max(1.0);
+    }
+
+    pub fn point_size(&self) -> f32 {
+        self.point_size
+    }
+
+    /// Scales the point size over the particle's age, from `start` at birth
+    /// to `end` at death. Particles with an infinite lifetime stay at `start`.
+    /// Any curve other than the default constant 1 draws quads.
+    pub fn set_size_curve(&mut self, start: f32, end: f32) {
+        self.size_curve = (start.max(0.0), end.max(0.0));
+    }
+
+    pub fn size_curve(&self) -> (f32, f32) {
+        self.size_curve
+    }
+
+    fn uses_quads(&self) -> bool {
+        self.textured
+            || self.round_points
+            || self.point_size > MAX_POINT_SIZE
+            || self.size_curve != (1.0, 1.0)
+    }
+
+    /// Draws each particle as a line from its previous position to its current
+    /// one, fading towards the tail by the ghost fade. Takes precedence over
+    /// ghosting and round points.
+    pub fn set_streaks(&mut self, enabled: bool) {
+        self.streaks = enabled;
+    }
+
+    /// Width in pixels of the streak lines, antialiased over their edges
+    pub fn set_line_width(&mut self, width: f32) {
+        self.line_width = width.max(0.0);
+    }
+
+    /// Draws only every `stride`th particle, a cheap preview at lower density
+    /// that leaves the simulation untouched. Clamped to at least 1.
+    pub fn set_render_stride(&mut self, stride: u32) {
+        self.render_stride = stride.max(1);
+    }
+
+    pub fn render_stride(&self) -> u32 {
+        self.render_stride
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    /// Also draws each particle at its previous position, with its alpha
+    /// scaled by `fade`, for a motion afterimage.
+    pub fn set_ghost(&mut self, enabled: bool, fade: f32) {
+        self.ghost = enabled;
+        self.ghost_fade = fade.clamp(0.0, 1.0);
+    }
+
+    pub fn is_ghost(&self) -> bool {
+        self.ghost
+    }
+
+    /// Replaces the attractors with a single one at `center`
+    pub fn set_gravity_center(&mut self, center: glam::Vec3) {
+        self.set_attractors(&[Attractor::new(center, GRAVITY_CENTER_MASS)]);
+    }
+
+    /// Points every particle is attracted to, each pulling independently.
+    /// Only the first `MAX_ATTRACTORS` are kept, none leaves particles
+    /// drifting freely.
+    pub fn set_attractors(&mut self, attractors: &[Attractor]) {
+        self.attractors.clear();
+        self.attractors
+            .extend_from_slice(&attractors[..attractors.len().min(MAX_ATTRACTORS)]);
+    }
+
+    pub fn attractors(&self) -> &[Attractor] {
+        &self.attractors
+    }
+
+    /// Keeps particles inside the `(min, max)` box by wrapping them around to
+    /// the opposite face when they leave it, so the field never depletes.
+    pub fn set_wrap_bounds(&mut self, bounds: Option<(glam::Vec3, glam::Vec3)>) {
+        self.wrap_bounds = bounds.map(|(min, max)| (min.min(max), min.max(max)));
+    }
+
+    /// Pushes particles apart when they come closer than `radius`, the push
+    /// growing linearly to `strength` as they overlap. Neighbors are found
+    /// through a uniform grid of `radius` sized cells, a radius of zero turns
+    /// repulsion off.
+    pub fn set_particle_repulsion(&mut self, radius: f32, strength: f32) {
+        self.repulsion_radius = radius.max(0.0);
+        self.repulsion_strength = strength;
+    }
+
+    pub fn repulsion_radius(&self) -> f32 {
+        self.repulsion_radius
+    }
+
+    /// Kills particles that come within `radius` of any attractor, zero
+    /// turns absorption off
+    pub fn set_absorb_radius(&mut self, radius: f32) {
+        self.absorb_radius = radius.max(0.0);
+    }
+
+    /// Moves the emitter, emission and forces `t` of the way from where they
+    /// were on the first call of a transition to `target`. Call it every frame
+    /// with `t` growing to 1, which ends the transition. Shapes and modes of
+    /// different kinds switch over halfway, for a smooth crossfade run a
+    /// second system and trade `set_intensity` between them. The buffers are
+    /// not resized, see `resize_capacity`.
+    pub fn lerp_to_config(&mut self, target: &Config, t: f32) {
+        let t = t.clamp(0.0, 1.0);
+        let origin = *self.config_origin.get_or_insert(ConfigOrigin {
+            position: self.position,
+            shape: self.emission_shape,
+            mode: self.emission_mode,
+            lifetime: self.lifetime,
+            absorb_radius: self.absorb_radius,
+            repulsion: (self.repulsion_radius, self.repulsion_strength),
+        });
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+
+        let info = &target.particle_system;
+        self.set_position(origin.position.lerp(info.position, t));
+        self.set_emission_shape(origin.shape.lerp(info.shape, t));
+        self.emission_mode = origin.mode.lerp(info.mode, t);
+        self.lifetime = if origin.lifetime.is_finite() && info.lifetime.is_finite() {
+            mix(origin.lifetime, info.lifetime)
+        } else if t < 0.5 {
+            origin.lifetime
+        } else {
+            info.lifetime
+        };
+
+        self.set_absorb_radius(mix(origin.absorb_radius, target.absorb_radius));
+        self.set_particle_repulsion(
+            mix(origin.repulsion.0, target.repulsion.0),
+            mix(origin.repulsion.1, target.repulsion.1),
+        );
+
+        if t >= 1.0 {
+            self.config_origin = None;
+        }
+    }
+
+    /// Simulates in steps of `step` seconds, as many as the frame time covers
+    /// up to a few per frame, so the result doesn't depend on the frame rate.
+    /// Frames shorter than a step don't simulate at all. `None` runs a single
+    /// update over each frame's time.
+    pub fn set_fixed_timestep(&mut self, step: Option<f32>) {
+        self.fixed_timestep = step.filter(|step| *step > 0.0);
+        self.step_accumulator = 0.0;
+    }
+
+    pub fn fixed_timestep(&self) -> Option<f32> {
+        self.fixed_timestep
+    }
+
+    /// Slows particles down by a factor of `exp(-drag)` every second, the
+    /// same whatever the frame rate. Zero leaves them moving ballistically.
+    pub fn set_drag(&mut self, drag: f32) {
+        self.drag = drag.max(0.0);
+    }
+
+    pub fn drag(&self) -> f32 {
+        self.drag
+    }
+
+    /// Acceleration applied uniformly to every particle, on top of the
+    /// attractors
+    pub fn set_wind(&mut self, wind: glam::Vec3) {
+        self.wind = wind;
+    }
+
+    pub fn wind(&self) -> glam::Vec3 {
+        self.wind
+    }
+
+    /// Stirs particles with a divergence-free curl noise field, accelerating
+    /// them by `strength` on average along swirls roughly `scale` units
+    /// across. Zero strength turns it off.
+    pub fn set_turbulence(&mut self, strength: f32, scale: f32) {
+        self.turbulence = (strength, scale.max(f32::EPSILON));
+    }
+
+    pub fn turbulence(&self) -> (f32, f32) {
+        self.turbulence
+    }
+
+    /// Stops simulating, `elapsed_time` freezes until `resume`
+    pub fn pause(&mut self) {
+        self.clock.pause(Instant::now());
+    }
+
+    pub fn resume(&mut self) {
+        self.clock.resume(Instant::now());
+    }
+
+    /// Turns the update and emit passes on or off, rendering is unaffected
+    pub fn set_simulation_enabled(&mut self, enabled: bool) {
+        self.simulation_enabled = enabled;
+    }
 
-        (render_pipeline, bind_group)
+    pub fn is_simulation_enabled(&self) -> bool {
+        self.simulation_enabled
     }
 
-    fn update_particles(&mut self, context: &mut RenderContext) {
-        let mut pass = context
-            .encoder_mut()
-            .begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Update Pass"),
-                timestamp_writes: None,
-            });
+    /// Turns drawing on or off while the simulation keeps running
+    pub fn set_rendering_enabled(&mut self, enabled: bool) {
+        self.rendering_enabled = enabled;
+    }
 
-        pass.set_pipeline(&self.update_pipeline);
-        pass.set_bind_group(0, &self.update_bind_group, &[]);
-        pass.dispatch_workgroups(self.max_particles.div_ceil(256), 1, 1);
+    pub fn is_rendering_enabled(&self) -> bool {
+        self.rendering_enabled
+    }
 
-        drop(pass);
+    /// Resets time, emission and the paused state, clearing the particles
+    /// unless `mode` is `Warm`
+    pub fn restart(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, mode: RestartMode) {
+        if mode == RestartMode::Reseed {
+            self.seed = std::hash::BuildHasher::hash_one(
+                &std::collections::hash_map::RandomState::new(),
+                self.seed,
+            ) as u32;
+        }
+
+        self.clock.restart(Instant::now());
+        self.emission_accumulator = 0.0;
+        self.next_pulse_time = 0.0;
+        self.burst_remaining = Self::burst_size(self.emission_mode);
+        self.pending_burst = 0;
+        self.start_fill();
+        self.completion_reported = false;
+
+        if mode != RestartMode::Warm {
+            self.clear(device, queue);
+        }
     }
 
-    fn compact_particles(&mut self, context: &mut RenderContext) {
+    /// Seed of the emission's random numbers, emission with hash sampling
+    /// repeats exactly for the same seed and timing
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Kills every particle, leaving time and emission untouched
+    pub fn clear(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        // Reset the indirect draw args
         let indirect_args = DrawIndirectArgs {
             vertex_count: 1,
             instance_count: 0,
             first_vertex: 0,
             first_instance: 0,
         };
-        context.queue().write_buffer(
+
+        queue.write_buffer(
             &self.compact_uniforms_buffer,
             0,
             bytemuck::cast_slice(&[indirect_args]),
         );
 
-        let mut pass = context
-            .encoder_mut()
-            .begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Compact Pass"),
-                timestamp_writes: None,
-            });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Clear Encoder"),
+        });
+        self.clear_buffers(&mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
 
-        pass.set_pipeline(&self.compact_pipeline);
-        pass.set_bind_group(0, &self.compact_bind_group, &[]);
-        pass.dispatch_workgroups(self.max_particles.div_ceil(256), 1, 1);
+        // Nothing is alive anymore, a count read back from before says otherwise
+        self.alive_count.clear(
+            self.emissions,
+            self.emitted_particles,
+            self.count_readback_state != CountReadbackState::Idle,
+        );
+    }
 
-        drop(pass);
+    /// Zeroes both particle buffers, a zeroed particle being dead
+    fn clear_buffers(&self, encoder: &mut wgpu::CommandEncoder) {
+        for buffer in &self.particles_buffers {
+            encoder.clear_buffer(buffer, 0, None);
+        }
     }
 
-    fn emit_particles(&mut self, context: &mut RenderContext) {
-        let count = match self.emission_mode {
-            ParticleEmissionMode::Continuous(rate) => (rate as f32 * 0.016) as u32,
-            ParticleEmissionMode::Burst(count) => count,
-        };
+    /// Replaces every particle with `particles`, truncated to the capacity
+    pub fn load_particles(&mut self, queue: &wgpu::Queue, particles: &[Particle]) {
+        let particles = &particles[..particles.len().min(self.max_particles as usize)];
+        self.write_particles(queue, particles, particles);
+    }
 
-        if count == 0 {
-            return;
+    /// Puts back the particles captured by `snapshot`. Fails without touching
+    /// the buffers when the snapshot doesn't fit in this system.
+    pub fn restore(
+        &mut self,
+        queue: &wgpu::Queue,
+        snapshot: &ParticleSnapshot,
+    ) -> Result<(), RestoreError> {
+        let count = snapshot.particles.len();
+        if count > self.max_particles as usize {
+            return Err(RestoreError::CapacityExceeded {
+                count: count.min(u32::MAX as usize) as u32,
+                capacity: self.max_particles,
+            });
         }
 
-        let emit_uniforms = EmitUniforms {
-            position: self.position.extend(1.0).to_array(),
-            count,
-            lifetime: self.lifetime,
-            shape: self.emission_shape as u32,
-            elapsed_time: self.elapsed_time(),
+        // Snapshots built by hand may leave out the previous state
+        let previous = if snapshot.previous.len() == count {
+            &snapshot.previous
+        } else {
+            &snapshot.particles
         };
+        self.write_particles(queue, &snapshot.particles, previous);
 
-        context.queue().write_buffer(
-            &self.emit_uniforms_buffer,
+        Ok(())
+    }
+
+    fn write_particles(&self, queue: &wgpu::Queue, particles: &[Particle], previous: &[Particle]) {
+        let indirect_args = DrawIndirectArgs {
+            vertex_count: 1,
+            instance_count: particles.len() as u32,
+            first_vertex: 0,
+            first_instance: 0,
+        };
+        queue.write_buffer(
+            &self.compact_uniforms_buffer,
             0,
-            bytemuck::cast_slice(&[emit_uniforms]),
+            bytemuck::cast_slice(&[indirect_args]),
         );
 
-        let mut pass = context
-            .encoder_mut()
-            .begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Emit Pass"),
-                timestamp_writes: None,
-            });
+        // Ids carry on past the loaded ones so new particles never share them
+        let next_id = particles
+            .iter()
+            .map(|particle| particle.id.wrapping_add(1))
+            .max()
+            .unwrap_or(0);
+        queue.write_buffer(
+            &self.compact_uniforms_buffer,
+            NEXT_ID_OFFSET,
+            bytemuck::bytes_of(&next_id),
+        );
 
-        pass.set_pipeline(&self.emit_pipeline);
-        pass.set_bind_group(0, &self.emit_bind_group, &[]);
-        pass.dispatch_workgroups(count.div_ceil(256), 1, 1);
+        // Zeroed slots are dead, so compaction drops whatever was there before
+        for (buffer, particles) in self.particles_buffers.iter().zip([particles, previous]) {
+            let mut data = vec![bytemuck::Zeroable::zeroed(); self.max_particles as usize];
+            data[..particles.len()].copy_from_slice(particles);
+            queue.write_buffer(buffer, 0, &self.precision.encode_particles(&data));
+        }
+    }
 
-        drop(pass);
+    /// Replaces every particle with a single one at `position` moving at
+    /// `velocity`, and stops emission, so its trajectory can be traced with
+    /// `read_particles`.
+    pub fn spawn_debug_particle(
+        &mut self,
+        queue: &wgpu::Queue,
+        position: glam::Vec3,
+        velocity: glam::Vec3,
+    ) {
+        self.emission_mode = ParticleEmissionMode::Burst(0);
+        self.burst_remaining = 0;
+        self.pending_burst = 0;
+        self.fill_rate = None;
+        self.filling = false;
+
+        let particle = Particle {
+            position: position.extend(1.0).to_array(),
+            velocity: velocity.extend(0.0).to_array(),
+            mass: 1.0,
+            lifetime: f32::INFINITY,
+            age: 0.0,
+            id: 0,
+            color: [1.0; 4],
+            generation: 0,
+            temperature: self.temperature,
+            padding: [0; 2],
+        };
+
+        self.load_particles(queue, &[particle]);
     }
 
-    fn render_particles(&self, context: &mut RenderContext) {
-        let view = context.view().clone();
-        let depth_view = context.depth_view().clone();
-        let mut pass = context
-            .encoder_mut()
-            .begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    depth_slice: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &depth_view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
+    fn read_buffer(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+        size: u64,
+    ) -> Result<Vec<u8>, ReadbackError> {
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
 
-        pass.set_pipeline(&self.render_pipeline);
-        pass.set_bind_group(0, &self.render_bind_group, &[]);
-        pass.draw_indirect(&self.compact_uniforms_buffer, 0);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let slice = staging_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+
+        device
+            .poll(wgpu::PollType::Wait)
+            .map_err(|_| ReadbackError::PollFailed)?;
+
+        match receiver.recv() {
+            Ok(Ok(())) => {}
+            _ => return Err(ReadbackError::MapFailed),
+        }
+
+        let data = slice.get_mapped_range().to_vec();
+        staging_buffer.unmap();
+
+        Ok(data)
+    }
+
+    /// Counts the invocations that did work in the emit and update shaders,
+    /// to check the dispatch sizes against the shaders' workgroup size
+    pub fn set_debug_dispatch(&mut self, enabled: bool) {
+        self.debug_dispatch = enabled;
     }
 
-    pub fn update(&mut self, context: &mut RenderContext) {
-        if self.is_paused() == false {
-            self.compact_particles(context);
-            self.update_particles(context);
-            self.emit_particles(context);
+    /// Dispatch statistics of the last submitted frame, needs
+    /// `set_debug_dispatch`. Stalls until the GPU is idle.
+    pub fn debug_dispatch_stats(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<DispatchStats, ReadbackError> {
+        let data = Self::read_buffer(
+            device,
+            queue,
+            &self.debug_counters_buffer,
+            2 * std::mem::size_of::<u32>() as u64,
+        )?;
+        let [emit_active, update_active]: [u32; 2] = bytemuck::pod_read_unaligned(&data);
+
+        Ok(DispatchStats {
+            emit_threads: self.emit_threads,
+            emit_active,
+            update_threads: self.update_threads,
+            update_active,
+        })
+    }
+
+    /// Reads the live particle count straight from the GPU counter, the one
+    /// `alive_count` reports a few frames late. This stalls until the GPU is
+    /// idle, so it is meant for tests and debugging rather than every frame.
+    pub fn read_alive_count(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<u32, ReadbackError> {
+        let args = Self::read_buffer(
+            device,
+            queue,
+            &self.compact_uniforms_buffer,
+            std::mem::size_of::<DrawIndirectArgs>() as u64,
+        )?;
+        let args: DrawIndirectArgs = bytemuck::pod_read_unaligned(&args);
+
+        // Emission can push the counter past capacity, only that many particles were written
+        Ok(args.instance_count.min(self.max_particles))
+    }
+
+    /// Copies the live particles back to the CPU. This stalls until the GPU is
+    /// idle, so it is meant for tooling and debugging rather than every frame.
+    pub fn read_particles(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<Particle>, ReadbackError> {
+        let count = self.read_alive_count(device, queue)?;
+        if count == 0 {
+            return Ok(Vec::new());
         }
 
-        self.render_particles(context);
+        let size = count as u64 * self.precision.particle_size();
+        let data = Self::read_buffer(device, queue, &self.particles_buffers[0], size)?;
+
+        Ok(self.precision.decode_particles(&data))
     }
 
-    pub fn set_render_uniforms(&mut self, context: &mut RenderContext, uniforms: RenderUniforms) {
-        context.queue().write_buffer(
-            &self.render_uniforms_buffer,
-            0,
-            bytemuck::cast_slice(&[uniforms]),
-        );
+    /// Captures both particle buffers for the live count, to be put back
+    /// with `restore`. Stalls like `read_particles`.
+    pub fn snapshot(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<ParticleSnapshot, ReadbackError> {
+        let count = self.read_alive_count(device, queue)?;
+        if count == 0 {
+            return Ok(ParticleSnapshot::default());
+        }
+
+        let size = count as u64 * self.precision.particle_size();
+        let particles = Self::read_buffer(device, queue, &self.particles_buffers[0], size)?;
+        let previous = Self::read_buffer(device, queue, &self.particles_buffers[1], size)?;
+
+        Ok(ParticleSnapshot {
+            particles: self.precision.decode_particles(&particles),
+            previous: self.precision.decode_particles(&previous),
+        })
     }
 
-    pub fn set_update_uniforms(&mut self, context: &mut RenderContext, uniforms: UpdateUniforms) {
-        context.queue().write_buffer(
+    /// Live particles per repulsion grid cell, binned the same way as the bin
+    /// shader, sorted by count with the fullest cell first. Empty while
+    /// repulsion is off. Stalls like `read_particles`.
+    pub fn read_grid_occupancy(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<GridCell>, ReadbackError> {
+        let radius = self.repulsion_radius;
+        if radius <= 0.0 {
+            return Ok(Vec::new());
+        }
+
+        let mut counts = std::collections::HashMap::new();
+        for particle in self.read_particles(device, queue)? {
+            if particle.age >= particle.lifetime {
+                continue;
+            }
+
+            let position = glam::Vec3::from_slice(&particle.position[..3]);
+            let cell = (position / radius).floor().as_ivec3();
+            *counts.entry(cell).or_insert(0) += 1;
+        }
+
+        let mut cells: Vec<GridCell> = counts
+            .into_iter()
+            .map(|(cell, count)| GridCell { cell, count })
+            .collect();
+        cells.sort_by_key(|cell| std::cmp::Reverse(cell.count));
+
+        Ok(cells)
+    }
+
+    fn recreate_pipelines(&mut self, device: &wgpu::Device) {
+        let precision = self.precision;
+
+        (self.emit_pipeline, self.emit_bind_group) = Self::create_emit_pipeline(
+            device,
+            precision,
+            &self.particles_buffers,
+            &self.emit_uniforms_buffer,
+            &self.compact_uniforms_buffer,
+            &self.debug_counters_buffer,
+            &self.projection_texture,
+            &self.projection_sampler,
+        );
+
+        (self.compact_pipeline, self.compact_bind_group) = Self::create_compact_pipeline(
+            device,
+            precision,
+            &self.particles_buffers,
+            &self.compact_uniforms_buffer,
+            &self.counts_buffer,
+        );
+
+        (self.update_pipeline, self.update_bind_group) = Self::create_update_pipeline(
+            device,
+            precision,
+            &self.particles_buffers,
             &self.update_uniforms_buffer,
-            0,
-            bytemuck::cast_slice(&[uniforms]),
+            &self.cell_heads_buffer,
+            &self.particle_next_buffer,
+            &self.debug_counters_buffer,
+            &self.velocity_field,
+            &self.compact_uniforms_buffer,
+            &self.counts_buffer,
+            &self.attractors_buffer,
         );
-    }
 
-    pub fn pause(&mut self) {
-        self.state = SimulationState::Paused;
+        (self.bin_pipeline, self.bin_bind_group) = Self::create_bin_pipeline(
+            device,
+            precision,
+            &self.particles_buffers,
+            &self.update_uniforms_buffer,
+            &self.compact_uniforms_buffer,
+            &self.cell_heads_buffer,
+            &self.particle_next_buffer,
+        );
+
+        (
+            self.sort_init_pipeline,
+            self.sort_pipeline,
+            self.sort_bind_group,
+        ) = Self::create_sort_pipeline(
+            device,
+            precision,
+            &self.particles_buffers,
+            &self.compact_uniforms_buffer,
+            &self.sort_uniforms_buffer,
+            &self.sort_keys_buffer,
+            &self.sort_indices_buffer,
+            &self.sort_stages_buffer,
+        );
+
+        self.recreate_render_pipelines(device);
     }
 
-    pub fn resume(&mut self) {
-        self.state = SimulationState::Playing;
+    fn recreate_render_pipelines(&mut self, device: &wgpu::Device) {
+        (
+            self.render_pipeline,
+            self.round_pipeline,
+            self.quad_pipeline,
+            self.streak_pipeline,
+            self.sprite_pipeline,
+            self.render_bind_group,
+        ) = Self::create_render_pipeline(
+            device,
+            self.precision,
+            self.target_format,
+            self.depth_test.then_some(self.depth_bias),
+            self.sample_count,
+            self.blend_mode,
+            &self.particles_buffers,
+            &self.render_uniforms_buffer,
+            &self.sort_indices_buffer,
+            &self.sprite_texture,
+            &self.sprite_sampler,
+        );
     }
 
-    pub fn restart(&mut self, queue: &wgpu::Queue) {
-        self.start_time = Instant::now();
-        self.state = SimulationState::Playing;
+    /// Reallocates the particle buffers to hold `max_particles`, keeping the
+    /// live particles. The capacity never shrinks below the live count nor
+    /// grows past the device's storage buffer limit. Returns the new capacity.
+    pub fn resize_capacity(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        max_particles: u32,
+    ) -> Result<u32, ReadbackError> {
+        let alive_count = self.read_alive_count(device, queue)?;
+
+        let particle_size = self.precision.particle_size();
+        let device_limit =
+            (device.limits().max_storage_buffer_binding_size as u64 / particle_size) as u32;
+        let max_particles = max_particles.max(alive_count).min(device_limit);
+
+        if max_particles == self.max_particles {
+            return Ok(max_particles);
+        }
+
+        let particles_buffers =
+            Self::create_particle_buffers(device, self.precision, max_particles);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Resize Encoder"),
+        });
+
+        let copy_size = alive_count as u64 * particle_size;
+        if copy_size > 0 {
+            for (src, dst) in self.particles_buffers.iter().zip(particles_buffers.iter()) {
+                encoder.copy_buffer_to_buffer(src, 0, dst, 0, copy_size);
+            }
+        }
 
-        // Reset the indirect draw args
         let indirect_args = DrawIndirectArgs {
             vertex_count: 1,
-            instance_count: 0,
+            instance_count: alive_count,
             first_vertex: 0,
             first_instance: 0,
         };
-
         queue.write_buffer(
             &self.compact_uniforms_buffer,
             0,
             bytemuck::cast_slice(&[indirect_args]),
         );
 
-        // Clear both particle buffers
-        let buffer_size = (self.max_particles as usize * std::mem::size_of::<Particle>()) as u64;
-        let zeros = vec![0u8; buffer_size as usize];
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.particles_buffers = particles_buffers;
+        (self.cell_heads_buffer, self.particle_next_buffer) =
+            Self::create_grid_buffers(device, max_particles);
+        (
+            self.sort_keys_buffer,
+            self.sort_indices_buffer,
+            self.sort_stages_buffer,
+        ) = Self::create_sort_buffers(device, max_particles);
+        self.max_particles = max_particles;
+        self.recreate_pipelines(device);
+
+        Ok(max_particles)
+    }
+
+    pub fn max_particles(&self) -> u32 {
+        self.max_particles
+    }
+
+    /// Buffer holding the live particles between frames, packed at the front
+    /// in the layout of `ParticlePrecision`. The buffers don't swap roles:
+    /// every frame compacts this buffer into the write buffer, updates back
+    /// into this one, appends new particles to it and renders from it. A
+    /// custom pass recorded between frames should modify particles here.
+    pub fn current_read_buffer(&self) -> &wgpu::Buffer {
+        &self.particles_buffers[0]
+    }
 
-        queue.write_buffer(&self.particles_buffers[0], 0, &zeros);
-        queue.write_buffer(&self.particles_buffers[1], 0, &zeros);
+    /// Scratch buffer the next frame compacts into, its contents are
+    /// overwritten before they are read again
+    pub fn current_write_buffer(&self) -> &wgpu::Buffer {
+        &self.particles_buffers[1]
     }
 
+    /// Wall-clock seconds since the start or the last restart, not counting
+    /// the time spent paused. Neither the fixed timestep nor the frame deltas
+    /// passed to `update` affect it.
     pub fn elapsed_time(&self) -> f32 {
-        self.start_time.elapsed().as_secs_f32()
+        self.clock.elapsed(Instant::now()).as_secs_f32()
     }
 
     pub fn is_paused(&self) -> bool {
-        self.state == SimulationState::Paused
+        self.clock.state() == SimulationState::Paused
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Total emitted over `steps` frames of `delta_time` each
+    fn emit_steps(rate: f32, steps: impl IntoIterator<Item = f32>) -> u32 {
+        let mut accumulator = 0.0;
+        steps
+            .into_iter()
+            .map(|delta_time| accumulate_emission(&mut accumulator, rate, delta_time))
+            .sum()
+    }
+
+    #[test]
+    fn emission_is_frame_rate_independent() {
+        for rate in [7.0, 1000.0, 2000.0] {
+            for fps in [30, 120] {
+                let total = emit_steps(rate, std::iter::repeat_n(1.0 / fps as f32, fps));
+                assert!(
+                    (total as f32 - rate).abs() <= 1.0,
+                    "{rate}/s at {fps} FPS emitted {total}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn emission_follows_irregular_frame_times() {
+        // 144 Hz frames, a 100 ms stutter, then 60 Hz frames, a second in all
+        let frame_times = std::iter::repeat_n(1.0 / 144.0, 72)
+            .chain([0.1])
+            .chain(std::iter::repeat_n(1.0 / 60.0, 24));
+        let elapsed: f32 = frame_times.clone().sum();
+        assert!((elapsed - 1.0).abs() < 1e-4);
+
+        for rate in [7.0, 1000.0, 2000.0] {
+            let total = emit_steps(rate, frame_times.clone());
+            assert!(
+                (total as f32 - rate).abs() <= 1.0,
+                "{rate}/s emitted {total}"
+            );
+        }
+    }
+
+    #[test]
+    fn drag_is_frame_rate_independent() {
+        for steps in [100, 20] {
+            let delta_time = 1.0 / steps as f32;
+            let speed = (0..steps).fold(5.0, |speed, _| speed * drag_factor(1.0, delta_time));
+            assert!(
+                (speed - 5.0 / std::f32::consts::E).abs() < 1e-4,
+                "{steps} steps ended at {speed}"
+            );
+        }
+    }
+
+    #[test]
+    fn clear_drops_readback_in_flight() {
+        let mut alive_count = AliveCount::default();
+        alive_count.start_readback(1, 500);
+        alive_count.finish_readback(Some(500));
+        assert_eq!(alive_count.count, 500);
+
+        // A readback starts, then the system restarts before it lands
+        alive_count.start_readback(2, 1000);
+        alive_count.clear(2, 1000, true);
+        assert_eq!(alive_count.count, 0);
+        alive_count.finish_readback(Some(1000));
+        assert_eq!(alive_count.count, 0);
+        assert_eq!((alive_count.emissions, alive_count.emitted), (2, 1000));
+
+        // Readbacks started after the clear count again
+        alive_count.start_readback(3, 1200);
+        alive_count.finish_readback(Some(200));
+        assert_eq!(alive_count.count, 200);
+        assert_eq!((alive_count.emissions, alive_count.emitted), (3, 1200));
+    }
+
+    #[test]
+    fn clock_stands_still_while_paused() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut clock = PausableClock::new(start);
+
+        clock.pause(at(200));
+        assert_eq!(clock.elapsed(at(200)), Duration::from_millis(200));
+        assert_eq!(clock.elapsed(at(700)), Duration::from_millis(200));
+
+        // Pausing again doesn't move the start of the pause
+        clock.pause(at(500));
+        clock.resume(at(700));
+        assert_eq!(clock.elapsed(at(900)), Duration::from_millis(400));
+
+        // Resuming while running changes nothing
+        clock.resume(at(900));
+        assert_eq!(clock.elapsed(at(900)), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn restart_while_paused_runs_again() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut clock = PausableClock::new(start);
+
+        clock.pause(at(200));
+        assert_eq!(clock.state(), SimulationState::Paused);
+
+        clock.restart(at(500));
+        assert_eq!(clock.state(), SimulationState::Playing);
+        assert_eq!(clock.elapsed(at(500)), Duration::ZERO);
+        assert_eq!(clock.elapsed(at(600)), Duration::from_millis(100));
+    }
+
+    fn gradient_uniforms() -> RenderUniforms {
+        RenderUniforms {
+            color_start: [1.0, 0.0, 0.0, 1.0],
+            color_end: [0.0, 0.0, 1.0, 1.0],
+            alpha_start: 1.0,
+            alpha_end: 0.0,
+            ..bytemuck::Zeroable::zeroed()
+        }
+    }
+
+    fn particle(age: f32, lifetime: f32) -> Particle {
+        Particle {
+            age,
+            lifetime,
+            color: [1.0; 4],
+            ..bytemuck::Zeroable::zeroed()
+        }
+    }
+
+    #[test]
+    fn particle_color_is_halfway_at_half_life() {
+        let color = gradient_uniforms().particle_color(&particle(1.0, 2.0));
+        assert!(
+            color.abs_diff_eq(glam::vec4(0.5, 0.0, 0.5, 0.5), 1e-6),
+            "{color}"
+        );
+    }
+
+    #[test]
+    fn immortal_particle_keeps_start_color() {
+        let color = gradient_uniforms().particle_color(&particle(100.0, f32::INFINITY));
+        assert_eq!(color, glam::vec4(1.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn dead_particle_is_transparent() {
+        let uniforms = gradient_uniforms();
+        assert_eq!(
+            uniforms.particle_color(&particle(2.0, 2.0)),
+            glam::Vec4::ZERO
+        );
+        assert_eq!(
+            uniforms.particle_color(&particle(3.0, 2.0)),
+            glam::Vec4::ZERO
+        );
+    }
+
+    // None on machines without any adapter, where the GPU tests are skipped
+    fn headless_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default())).ok()?;
+
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            required_limits: adapter.limits(),
+            ..Default::default()
+        }))
+        .ok()
+    }
+
+    fn burst_system(device: &wgpu::Device) -> ParticleSystem {
+        let info = ParticleSystemInfo {
+            position: glam::Vec3::ZERO,
+            shape: ParticleEmissionShape::Sphere,
+            mode: ParticleEmissionMode::Burst(100),
+            lifetime: 1.0,
+            lifetime_variance: 0.0,
+            precision: ParticlePrecision::default(),
+            depth_bias: wgpu::DepthBiasState::default(),
+            blend_mode: BlendMode::Additive,
+        };
+        ParticleSystem::new(device, wgpu::TextureFormat::Rgba16Float, info)
+    }
+
+    #[test]
+    fn restart_resets_alive_count_and_finished() {
+        let Some((device, queue)) = headless_device() else {
+            eprintln!("No adapter found, skipping");
+            return;
+        };
+        let mut system = burst_system(&device);
+
+        // The burst went out and the readback saw the particles alive
+        system.burst_remaining = 0;
+        system.emissions = 1;
+        system.emitted_particles = 100;
+        system.alive_count.start_readback(1, 100);
+        system.alive_count.finish_readback(Some(100));
+        assert_eq!(system.alive_count(), 100);
+        assert!(!system.is_finished());
+
+        // A warm restart keeps the particles and the count
+        system.restart(&device, &queue, RestartMode::Warm);
+        assert_eq!(system.alive_count(), 100);
+
+        // Restarting kills everything and owes the burst again
+        system.restart(&device, &queue, RestartMode::Cold);
+        assert_eq!(system.alive_count(), 0);
+        assert!(!system.is_finished());
+
+        // It only finishes once the new burst went out and died
+        system.burst_remaining = 0;
+        system.emissions += 1;
+        system.emitted_particles += 100;
+        assert!(!system.is_finished());
+        system
+            .alive_count
+            .start_readback(system.emissions, system.emitted_particles);
+        system.alive_count.finish_readback(Some(0));
+        assert!(system.is_finished());
+    }
+
+    #[test]
+    fn restart_empties_gpu_counter() {
+        let Some((device, queue)) = headless_device() else {
+            eprintln!("No adapter found, skipping");
+            return;
+        };
+        let mut system = burst_system(&device);
+
+        system.load_particles(&queue, &vec![particle(0.0, 1.0); 50]);
+        assert_eq!(system.read_alive_count(&device, &queue).unwrap(), 50);
+
+        system.restart(&device, &queue, RestartMode::Cold);
+        assert_eq!(system.read_alive_count(&device, &queue).unwrap(), 0);
+        assert!(system.read_particles(&device, &queue).unwrap().is_empty());
     }
 }
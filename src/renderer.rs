@@ -1,14 +1,117 @@
-use {std::sync::Arc, winit::window::Window};
+use {
+    crate::{grid_overlay::GridOverlay, particle_system::GridCell, text_renderer::TextRenderer},
+    std::{
+        path::Path,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+    },
+    winit::window::Window,
+};
+
+const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// WebGPU rasterizes point primitives exactly one pixel wide on every backend
+pub const MAX_POINT_SIZE: f32 = 1.0;
+
+/// Curve used to map the HDR scene into the displayable range
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tonemap {
+    /// Values above 1.0 are clipped
+    #[default]
+    None,
+    Reinhard,
+    Aces,
+}
+
+/// Whether `end_frame` waits for the GPU before presenting
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Frames may overlap on the GPU
+    #[default]
+    NonBlocking,
+    /// Every frame finishes on the GPU before it is presented, for captures
+    /// and benchmarks that need fully resolved frames
+    Blocking,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniforms {
+    mode: u32,
+    exposure: f32,
+}
+
+/// The scene targets sized after the surface, recreated on every resize
+struct FrameTargets {
+    // None when built without a depth buffer
+    depth_view: Option<wgpu::TextureView>,
+    hdr_texture: wgpu::TextureView,
+    // Multisampled scene target resolved into the HDR texture, None with a
+    // single sample
+    msaa_texture: Option<wgpu::TextureView>,
+    sample_count: u32,
+}
+
+impl FrameTargets {
+    fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        depth_buffer: bool,
+    ) -> Self {
+        Self {
+            depth_view: depth_buffer
+                .then(|| Renderer::create_depth_texture(device, width, height, sample_count)),
+            hdr_texture: Renderer::create_hdr_texture(device, width, height),
+            msaa_texture: (sample_count > 1)
+                .then(|| Renderer::create_msaa_texture(device, width, height, sample_count)),
+            sample_count,
+        }
+    }
+
+    /// Recreates every target at the new size, keeping the depth buffer and
+    /// sample count
+    fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        *self = Self::new(
+            device,
+            width,
+            height,
+            self.sample_count,
+            self.depth_view.is_some(),
+        );
+    }
+}
 
 pub struct Renderer {
+    #[allow(unused)]
     window: Arc<Window>,
+    #[allow(unused)]
     instance: wgpu::Instance,
+    #[allow(unused)]
     adapter: wgpu::Adapter,
     device: wgpu::Device,
     queue: wgpu::Queue,
     surface: wgpu::Surface<'static>,
     surface_config: wgpu::SurfaceConfiguration,
-    depth_texture: wgpu::TextureView,
+    alpha_modes: Vec<wgpu::CompositeAlphaMode>,
+    present_modes: Vec<wgpu::PresentMode>,
+    targets: FrameTargets,
+    clear_color: Option<wgpu::Color>,
+    tonemap: Tonemap,
+    exposure: f32,
+    tonemap_uniforms_buffer: wgpu::Buffer,
+    tonemap_bind_group_layout: wgpu::BindGroupLayout,
+    tonemap_bind_group: wgpu::BindGroup,
+    tonemap_pipeline: wgpu::RenderPipeline,
+    accumulation: Option<f32>,
+    fade_pipeline: wgpu::RenderPipeline,
+    text_renderer: TextRenderer,
+    grid_overlay: GridOverlay,
+    sync_mode: SyncMode,
+    device_lost: Arc<AtomicBool>,
 }
 
 #[derive(Debug)]
@@ -16,6 +119,7 @@ pub enum RendererError {
     AdapterNotFound,
     DeviceRequestFailed,
     SurfaceCreationFailed,
+    StorageBufferTooLarge { requested: u64, supported: u64 },
 }
 
 impl std::fmt::Display for RendererError {
@@ -24,49 +128,189 @@ impl std::fmt::Display for RendererError {
             RendererError::AdapterNotFound => write!(f, "Failed to find a suitable GPU adapter"),
             RendererError::DeviceRequestFailed => write!(f, "Failed to request device"),
             RendererError::SurfaceCreationFailed => write!(f, "Failed to create surface"),
+            RendererError::StorageBufferTooLarge {
+                requested,
+                supported,
+            } => write!(
+                f,
+                "Particle buffers need {requested} bytes but the adapter only supports {supported} bytes per storage buffer"
+            ),
         }
     }
 }
 
 impl std::error::Error for RendererError {}
 
+#[derive(Debug)]
+pub enum CaptureError {
+    /// Only 8-bit RGBA and BGRA surfaces can be written as PNG
+    UnsupportedFormat(wgpu::TextureFormat),
+    MapFailed,
+    PollFailed,
+    Io(std::io::Error),
+    Encoding(png::EncodingError),
+}
+
+impl std::fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureError::UnsupportedFormat(format) => {
+                write!(f, "Can't capture frames from a {format:?} surface")
+            }
+            CaptureError::MapFailed => write!(f, "Failed to map capture buffer"),
+            CaptureError::PollFailed => write!(f, "Failed to wait for the device"),
+            CaptureError::Io(e) => write!(f, "Failed to create capture file: {e}"),
+            CaptureError::Encoding(e) => write!(f, "Failed to encode PNG: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+#[derive(Clone, Debug)]
+pub struct RendererBuilder {
+    backends: wgpu::Backends,
+    power_preference: wgpu::PowerPreference,
+    present_mode: wgpu::PresentMode,
+    storage_buffer_size: u64,
+    depth_buffer: bool,
+    sample_count: u32,
+}
+
+impl Default for RendererBuilder {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            storage_buffer_size: 0,
+            depth_buffer: true,
+            sample_count: 1,
+        }
+    }
+}
+
+impl RendererBuilder {
+    pub fn backends(mut self, backends: wgpu::Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    pub fn present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    /// Size in bytes of the largest storage buffer that will be bound, used to
+    /// negotiate the device limits with the adapter.
+    pub fn storage_buffer_size(mut self, storage_buffer_size: u64) -> Self {
+        self.storage_buffer_size = storage_buffer_size;
+        self
+    }
+
+    /// Without a depth buffer no depth texture is allocated and scene passes
+    /// have no depth attachment, which saves memory and bandwidth when
+    /// everything is blended additively. Particle systems drawn with it need
+    /// `ParticleSystem::set_depth_test(device, false)`.
+    pub fn depth_buffer(mut self, enabled: bool) -> Self {
+        self.depth_buffer = enabled;
+        self
+    }
+
+    /// Samples per pixel of the scene target, particle systems drawn with it
+    /// need `ParticleSystem::set_sample_count`. Falls back to a single sample
+    /// if the adapter can't multisample the scene formats with this count.
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub async fn build(self, window: Arc<Window>) -> Result<Renderer, RendererError> {
+        Renderer::create(window, self).await
+    }
+}
+
 impl Renderer {
-    pub async fn new(window: Arc<Window>) -> Result<Self, RendererError> {
+    pub fn builder() -> RendererBuilder {
+        RendererBuilder::default()
+    }
+
+    pub async fn new(window: Arc<Window>, storage_buffer_size: u64) -> Result<Self, RendererError> {
+        Self::builder()
+            .storage_buffer_size(storage_buffer_size)
+            .build(window)
+            .await
+    }
+
+    async fn create(window: Arc<Window>, options: RendererBuilder) -> Result<Self, RendererError> {
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
+            backends: options.backends,
             ..Default::default()
         });
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
+                power_preference: options.power_preference,
                 force_fallback_adapter: false,
                 compatible_surface: None,
             })
             .await
             .map_err(|_| RendererError::AdapterNotFound)?;
 
+        let required_limits =
+            Self::negotiate_limits(&adapter.limits(), options.storage_buffer_size)?;
+
+        println!(
+            "Max storage buffer binding size: {} bytes (adapter supports {} bytes)",
+            required_limits.max_storage_buffer_binding_size,
+            adapter.limits().max_storage_buffer_binding_size
+        );
+
+        let (sample_count, required_features) =
+            Self::negotiate_sample_count(&adapter, options.sample_count);
+
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: Some("Device"),
                 trace: wgpu::Trace::Off,
-                required_features: wgpu::Features::empty(),
-                required_limits: wgpu::Limits {
-                    max_storage_buffer_binding_size: 268435456,
-                    ..Default::default()
-                },
+                required_features,
+                required_limits,
                 memory_hints: wgpu::MemoryHints::Performance,
             })
             .await
             .map_err(|_| RendererError::DeviceRequestFailed)?;
 
+        let device_lost = Arc::new(AtomicBool::new(false));
+
+        let flag = device_lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            if reason != wgpu::DeviceLostReason::Destroyed {
+                eprintln!("GPU device lost ({reason:?}): {message}");
+            }
+            flag.store(true, Ordering::SeqCst);
+        });
+
+        // Once the device is gone every call errors, don't panic on those
+        let flag = device_lost.clone();
+        device.on_uncaptured_error(Box::new(move |error| {
+            if !flag.load(Ordering::SeqCst) {
+                panic!("wgpu error: {error}");
+            }
+        }));
+
         let surface = instance
             .create_surface(window.clone())
             .map_err(|_| RendererError::SurfaceCreationFailed)?;
 
         let surface_caps = surface.get_capabilities(&adapter);
 
-        let present_mode = wgpu::PresentMode::AutoVsync;
+        let present_mode =
+            Self::supported_present_mode(&surface_caps.present_modes, options.present_mode);
 
         let surface_format = surface_caps
             .formats
@@ -99,21 +343,156 @@ impl Renderer {
 
         surface.configure(&device, &surface_config);
 
-        let depth_texture = Self::create_depth_texture(&device, size.width, size.height);
+        let targets = FrameTargets::new(
+            &device,
+            size.width,
+            size.height,
+            sample_count,
+            options.depth_buffer,
+        );
+
+        let tonemap = Tonemap::default();
+        let exposure = 1.0;
+
+        let tonemap_uniforms_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tonemap Uniforms Buffer"),
+            size: std::mem::size_of::<TonemapUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (tonemap_pipeline, tonemap_bind_group_layout) =
+            Self::create_tonemap_pipeline(&device, surface_format);
+
+        let tonemap_bind_group = Self::create_tonemap_bind_group(
+            &device,
+            &tonemap_bind_group_layout,
+            &tonemap_uniforms_buffer,
+            &targets.hdr_texture,
+        );
+
+        let fade_pipeline = Self::create_fade_pipeline(&device, sample_count);
+
+        let text_renderer = TextRenderer::new(&device, &queue, surface_format);
+        let grid_overlay =
+            GridOverlay::new(&device, HDR_FORMAT, options.depth_buffer, sample_count);
 
-        Ok(Self {
+        let renderer = Self {
             instance,
             adapter,
             device,
             queue,
             surface,
             surface_config,
-            depth_texture,
+            alpha_modes: surface_caps.alpha_modes,
+            present_modes: surface_caps.present_modes,
+            targets,
+            clear_color: Some(wgpu::Color::BLACK),
+            tonemap,
+            exposure,
+            tonemap_uniforms_buffer,
+            tonemap_bind_group_layout,
+            tonemap_bind_group,
+            tonemap_pipeline,
+            accumulation: None,
+            fade_pipeline,
+            text_renderer,
+            grid_overlay,
+            sync_mode: SyncMode::NonBlocking,
+            device_lost,
             window,
+        };
+
+        renderer.write_tonemap_uniforms();
+
+        Ok(renderer)
+    }
+
+    /// `present_mode` if the surface supports it, `AutoVsync` otherwise
+    fn supported_present_mode(
+        present_modes: &[wgpu::PresentMode],
+        present_mode: wgpu::PresentMode,
+    ) -> wgpu::PresentMode {
+        // The automatic modes always resolve to something the surface supports
+        if matches!(
+            present_mode,
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync
+        ) || present_modes.contains(&present_mode)
+        {
+            present_mode
+        } else {
+            wgpu::PresentMode::AutoVsync
+        }
+    }
+
+    /// Requests the default limits, raised to fit `storage_buffer_size` when
+    /// the adapter allows it.
+    fn negotiate_limits(
+        supported: &wgpu::Limits,
+        storage_buffer_size: u64,
+    ) -> Result<wgpu::Limits, RendererError> {
+        let supported_size =
+            (supported.max_storage_buffer_binding_size as u64).min(supported.max_buffer_size);
+
+        if storage_buffer_size > supported_size {
+            return Err(RendererError::StorageBufferTooLarge {
+                requested: storage_buffer_size,
+                supported: supported_size,
+            });
+        }
+
+        let defaults = wgpu::Limits::default();
+
+        Ok(wgpu::Limits {
+            max_storage_buffer_binding_size: storage_buffer_size
+                .max(defaults.max_storage_buffer_binding_size as u64)
+                .min(supported.max_storage_buffer_binding_size as u64)
+                as u32,
+            max_buffer_size: storage_buffer_size
+                .max(defaults.max_buffer_size)
+                .min(supported.max_buffer_size),
+            ..defaults
         })
     }
 
-    fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+    /// Picks `requested` samples per pixel if the adapter can multisample
+    /// both the HDR and depth formats with it, otherwise one. Returns the
+    /// count with the device features it needs.
+    fn negotiate_sample_count(adapter: &wgpu::Adapter, requested: u32) -> (u32, wgpu::Features) {
+        if requested <= 1 {
+            return (1, wgpu::Features::empty());
+        }
+
+        // WebGPU only guarantees 4x, other counts are adapter specific
+        let features = match requested {
+            4 => wgpu::Features::empty(),
+            _ => wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+        };
+
+        let supported = adapter.features().contains(features)
+            && [HDR_FORMAT, wgpu::TextureFormat::Depth32Float]
+                .into_iter()
+                .all(|format| {
+                    adapter
+                        .get_texture_format_features(format)
+                        .flags
+                        .sample_count_supported(requested)
+                });
+
+        if supported {
+            (requested, features)
+        } else {
+            eprintln!("{requested}x MSAA is not supported by the adapter, falling back to 1x");
+            (1, wgpu::Features::empty())
+        }
+    }
+
+    fn create_depth_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> wgpu::TextureView {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Depth Texture"),
             size: wgpu::Extent3d {
@@ -122,9 +501,38 @@ impl Renderer {
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Depth32Float,
+            // Copied from by read_depth, multisampled textures can't be
+            usage: if sample_count > 1 {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC
+            },
+            view_formats: &[],
+        });
+
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    fn create_msaa_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> wgpu::TextureView {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("MSAA Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             view_formats: &[],
         });
@@ -132,6 +540,188 @@ impl Renderer {
         texture.create_view(&wgpu::TextureViewDescriptor::default())
     }
 
+    fn create_hdr_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("HDR Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    fn create_tonemap_pipeline(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+    ) -> (wgpu::RenderPipeline, wgpu::BindGroupLayout) {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Tonemap Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/tonemap.wgsl").into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tonemap Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tonemap Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Tonemap Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        (pipeline, bind_group_layout)
+    }
+
+    /// Pipeline multiplying the HDR target by the blend constant
+    fn create_fade_pipeline(device: &wgpu::Device, sample_count: u32) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Fade Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/fade.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Fade Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let fade = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::Constant,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Fade Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: fade,
+                        alpha: fade,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    fn create_tonemap_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniforms_buffer: &wgpu::Buffer,
+        hdr_texture: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tonemap Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniforms_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(hdr_texture),
+                },
+            ],
+        })
+    }
+
+    fn write_tonemap_uniforms(&self) {
+        let mode = match self.tonemap {
+            Tonemap::None => 0,
+            Tonemap::Reinhard => 1,
+            Tonemap::Aces => 2,
+        };
+
+        let uniforms = TonemapUniforms {
+            mode,
+            exposure: self.exposure,
+        };
+
+        self.queue.write_buffer(
+            &self.tonemap_uniforms_buffer,
+            0,
+            bytemuck::cast_slice(&[uniforms]),
+        );
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
         if width == 0 || height == 0 {
             return;
@@ -141,19 +731,220 @@ impl Renderer {
         self.surface_config.height = height;
         self.surface.configure(&self.device, &self.surface_config);
 
-        self.depth_texture = Self::create_depth_texture(&self.device, width, height);
+        self.targets.resize(&self.device, width, height);
+        self.tonemap_bind_group = Self::create_tonemap_bind_group(
+            &self.device,
+            &self.tonemap_bind_group_layout,
+            &self.tonemap_uniforms_buffer,
+            &self.targets.hdr_texture,
+        );
+    }
+
+    /// Selects how the HDR scene is mapped to the surface at the end of the
+    /// frame. Additive particles can accumulate well above 1.0, a tonemap lets
+    /// dense regions glow towards white instead of clipping.
+    pub fn set_tonemap(&mut self, tonemap: Tonemap) {
+        self.tonemap = tonemap;
+        self.write_tonemap_uniforms();
+    }
+
+    pub fn tonemap(&self) -> Tonemap {
+        self.tonemap
+    }
+
+    /// Scales scene colors before tonemapping
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure.max(0.0);
+        self.write_tonemap_uniforms();
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    /// Switches the surface to premultiplied-alpha compositing so the window
+    /// background shows through where nothing is drawn. The window itself must
+    /// be created transparent. Returns false if the surface does not support it.
+    pub fn set_transparent(&mut self, transparent: bool) -> bool {
+        let alpha_mode = if transparent {
+            wgpu::CompositeAlphaMode::PreMultiplied
+        } else {
+            self.alpha_modes
+                .first()
+                .copied()
+                .unwrap_or(wgpu::CompositeAlphaMode::Auto)
+        };
+
+        if !self.alpha_modes.contains(&alpha_mode) {
+            return false;
+        }
+
+        self.surface_config.alpha_mode = alpha_mode;
+        self.surface.configure(&self.device, &self.surface_config);
+
+        true
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.surface_config.alpha_mode == wgpu::CompositeAlphaMode::PreMultiplied
+    }
+
+    /// Reconfigures the surface to present with `present_mode`, falling back
+    /// to `AutoVsync` if the surface doesn't support it. Returns the mode in
+    /// use.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        let present_mode = Self::supported_present_mode(&self.present_modes, present_mode);
+
+        if present_mode != self.surface_config.present_mode {
+            self.surface_config.present_mode = present_mode;
+            self.surface.configure(&self.device, &self.surface_config);
+        }
+
+        present_mode
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.surface_config.present_mode
+    }
+
+    /// Present modes the surface supports, besides the automatic ones which
+    /// are always accepted
+    pub fn supported_present_modes(&self) -> &[wgpu::PresentMode] {
+        &self.present_modes
+    }
+
+    /// Reads back the raw depth in [0, 1] at pixel `(x, y)` as left by the last
+    /// submitted frame, `None` if the pixel is outside the surface or the
+    /// readback failed or there is no single-sampled depth buffer. Stalls
+    /// until the GPU is idle, meant for debugging.
+    /// Use `Camera::linearize_depth` to turn it into a distance.
+    pub fn read_depth(&self, x: u32, y: u32) -> Option<f32> {
+        let depth_view = self.targets.depth_view.as_ref()?;
+        if self.targets.sample_count > 1
+            || x >= self.surface_config.width
+            || y >= self.surface_config.height
+        {
+            return None;
+        }
+
+        // Rows of a texture copy must be aligned, even for a single texel
+        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Depth Readback Buffer"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Depth Readback Encoder"),
+            });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: depth_view.texture(),
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::DepthOnly,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &staging_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: Some(1),
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let slice = staging_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+
+        self.device.poll(wgpu::PollType::Wait).ok()?;
+        receiver.recv().ok()?.ok()?;
+
+        let depth = bytemuck::pod_read_unaligned(&slice.get_mapped_range()[..4]);
+        staging_buffer.unmap();
+
+        Some(depth)
+    }
+
+    /// Color the frame starts from. With `None` only depth is reset, so
+    /// particles can be layered over a scene already drawn into the frame.
+    /// Transparent surfaces always clear to transparent.
+    pub fn set_clear_color(&mut self, clear_color: Option<wgpu::Color>) {
+        self.clear_color = clear_color;
+    }
+
+    /// Keeps the previous frames in the HDR target instead of clearing it,
+    /// scaled by `fade` each frame, so particles leave light trails. The
+    /// trails are lost on resize.
+    pub fn set_accumulation(&mut self, enabled: bool, fade: f32) {
+        self.accumulation = enabled.then_some(fade.clamp(0.0, 1.0));
+    }
+
+    pub fn accumulation(&self) -> Option<f32> {
+        self.accumulation
+    }
+
+    pub fn set_sync_mode(&mut self, sync_mode: SyncMode) {
+        self.sync_mode = sync_mode;
+    }
+
+    pub fn sync_mode(&self) -> SyncMode {
+        self.sync_mode
+    }
+
+    pub fn has_depth_buffer(&self) -> bool {
+        self.targets.depth_view.is_some()
+    }
+
+    /// Samples per pixel of the scene target, after falling back from what
+    /// the builder requested
+    pub fn sample_count(&self) -> u32 {
+        self.targets.sample_count
+    }
+
+    /// Largest point primitive the backend draws, in pixels. Particle systems
+    /// draw larger points as screen-aligned quads instead.
+    pub fn max_point_size(&self) -> f32 {
+        MAX_POINT_SIZE
+    }
+
+    /// Set once the GPU device has been lost, after which the renderer and
+    /// everything created from its device must be recreated.
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::SeqCst)
     }
 
     pub fn begin_frame(&self) -> Result<RenderContext<'_>, wgpu::SurfaceError> {
         let surface = &self.surface;
-        let depth_view = &self.depth_texture;
+        let depth_view = self.targets.depth_view.as_ref();
 
         let output = surface.get_current_texture()?;
-        let view = output
+        let surface_view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
+        // Scene passes draw into the multisampled target and resolve into the
+        // HDR texture the tonemap reads
+        let targets = &self.targets;
+        let (view, resolve_target) = match &targets.msaa_texture {
+            Some(msaa_texture) => (msaa_texture.clone(), Some(targets.hdr_texture.clone())),
+            None => (targets.hdr_texture.clone(), None),
+        };
 
-        let encoder = self
+        let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Main Encoder"),
@@ -161,24 +952,272 @@ impl Renderer {
 
         let queue = &self.queue;
 
+        let mut clear_color = self.clear_color.map(|color| {
+            if self.is_transparent() {
+                wgpu::Color::TRANSPARENT
+            } else {
+                color
+            }
+        });
+
+        // Fade the last frame in place, the frame then only resets depth
+        if let Some(fade) = self.accumulation {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Fade Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    depth_slice: None,
+                    resolve_target: resolve_target.as_ref(),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            let fade = fade as f64;
+            pass.set_pipeline(&self.fade_pipeline);
+            pass.set_blend_constant(wgpu::Color {
+                r: fade,
+                g: fade,
+                b: fade,
+                a: fade,
+            });
+            pass.draw(0..3, 0..1);
+            drop(pass);
+
+            clear_color = None;
+        }
+
         Ok(RenderContext {
             output,
             view,
+            resolve_target,
+            surface_view,
+            overlay: Vec::new(),
             depth_view,
             encoder,
             queue,
+            clear_color,
+            cleared: false,
         })
     }
 
+    /// Overlays `lines` of text in the top-left corner. Text is drawn after
+    /// tonemapping, on top of the whole frame.
+    pub fn draw_text(&self, frame: &mut RenderContext, lines: &[String]) {
+        frame.overlay.extend_from_slice(lines);
+    }
+
+    /// Outlines the repulsion grid `cells`, colored by particle count, as
+    /// read back by `ParticleSystem::read_grid_occupancy`. Drawn into the
+    /// scene immediately, so call it after the particle systems.
+    pub fn draw_grid(
+        &self,
+        frame: &mut RenderContext,
+        cells: &[GridCell],
+        cell_size: f32,
+        view_proj: glam::Mat4,
+    ) {
+        self.grid_overlay.draw(frame, cells, cell_size, view_proj);
+    }
+
+    /// Finishes the frame and presents it. Everything recorded into the
+    /// frame's encoder, by any number of particle systems, goes out in a
+    /// single submit.
     pub fn end_frame(&self, frame: RenderContext) {
+        self.submit_all(frame, std::iter::empty());
+    }
+
+    /// Like `end_frame`, but also submits `command_buffers` recorded outside
+    /// the frame. They are submitted ahead of the frame's own commands, in the
+    /// same single submit.
+    pub fn submit_all(
+        &self,
+        mut frame: RenderContext,
+        command_buffers: impl IntoIterator<Item = wgpu::CommandBuffer>,
+    ) {
+        // Nothing drew this frame, the HDR target still holds the last one
+        if !frame.cleared && frame.clear_color.is_some() {
+            let (color_load, _) = frame.load_ops();
+            frame
+                .encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Clear Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &frame.view,
+                        depth_slice: None,
+                        resolve_target: frame.resolve_target.as_ref(),
+                        ops: wgpu::Operations {
+                            load: color_load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+        }
+
+        self.encode_tonemap(&mut frame.encoder, &frame.surface_view);
+
+        if !frame.overlay.is_empty() {
+            let lines = std::mem::take(&mut frame.overlay);
+            self.text_renderer.draw(&mut frame, &lines);
+        }
+
         let queue = frame.queue;
         let output = frame.output;
         let encoder = frame.encoder.finish();
 
-        queue.submit(std::iter::once(encoder));
+        queue.submit(command_buffers.into_iter().chain(std::iter::once(encoder)));
+
+        if self.sync_mode == SyncMode::Blocking
+            && let Err(e) = self.device.poll(wgpu::PollType::Wait)
+        {
+            eprintln!("Failed to wait for the frame: {e}");
+        }
+
         output.present();
     }
 
+    /// Maps the HDR scene into `view`, which has the surface format
+    fn encode_tonemap(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tonemap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        pass.set_pipeline(&self.tonemap_pipeline);
+        pass.set_bind_group(0, &self.tonemap_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Saves the last submitted frame to `path` as a PNG, tonemapped like the
+    /// surface but without the text overlay. The surface texture can't be
+    /// copied from, so the scene is tonemapped again into an offscreen
+    /// texture. Stalls until the GPU is idle.
+    pub fn capture_frame(&self, path: &Path) -> Result<(), CaptureError> {
+        let format = self.surface_config.format;
+        let swap_red_blue = match format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            _ => return Err(CaptureError::UnsupportedFormat(format)),
+        };
+
+        let (width, height) = (self.surface_config.width, self.surface_config.height);
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Buffer rows are padded to the copy alignment
+        let row_size = width * 4;
+        let padded_row_size = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Capture Readback Buffer"),
+            size: padded_row_size as u64 * height as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Capture Encoder"),
+            });
+
+        self.encode_tonemap(&mut encoder, &view);
+
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &staging_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(height),
+                },
+            },
+            size,
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let slice = staging_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+
+        self.device
+            .poll(wgpu::PollType::Wait)
+            .map_err(|_| CaptureError::PollFailed)?;
+        match receiver.recv() {
+            Ok(Ok(())) => {}
+            _ => return Err(CaptureError::MapFailed),
+        }
+
+        let mut pixels = Vec::with_capacity((row_size * height) as usize);
+        for row in slice
+            .get_mapped_range()
+            .chunks_exact(padded_row_size as usize)
+        {
+            pixels.extend_from_slice(&row[..row_size as usize]);
+        }
+        staging_buffer.unmap();
+
+        if swap_red_blue {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        let file = std::fs::File::create(path).map_err(CaptureError::Io)?;
+        let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(CaptureError::Encoding)?;
+        writer
+            .write_image_data(&pixels)
+            .map_err(CaptureError::Encoding)?;
+        writer.finish().map_err(CaptureError::Encoding)
+    }
+
     pub fn device(&self) -> &wgpu::Device {
         &self.device
     }
@@ -187,33 +1226,155 @@ impl Renderer {
         &self.queue
     }
 
+    pub fn max_storage_buffer_binding_size(&self) -> u32 {
+        self.device.limits().max_storage_buffer_binding_size
+    }
+
     pub fn surface_format(&self) -> wgpu::TextureFormat {
         self.surface_config.format
     }
+
+    /// Format of the view scene passes render into
+    pub fn target_format(&self) -> wgpu::TextureFormat {
+        HDR_FORMAT
+    }
 }
 
 pub struct RenderContext<'a> {
     output: wgpu::SurfaceTexture,
     view: wgpu::TextureView,
-    depth_view: &'a wgpu::TextureView,
+    resolve_target: Option<wgpu::TextureView>,
+    surface_view: wgpu::TextureView,
+    overlay: Vec<String>,
+    depth_view: Option<&'a wgpu::TextureView>,
     encoder: wgpu::CommandEncoder,
     queue: &'a wgpu::Queue,
+    clear_color: Option<wgpu::Color>,
+    cleared: bool,
 }
 
 impl<'a> RenderContext<'a> {
+    /// Scene target, multisampled when the renderer uses MSAA
     pub fn view(&self) -> &wgpu::TextureView {
         &self.view
     }
 
+    /// Texture scene passes must resolve `view` into, `None` without MSAA
+    pub fn resolve_target(&self) -> Option<&wgpu::TextureView> {
+        self.resolve_target.as_ref()
+    }
+
+    pub fn surface_view(&self) -> &wgpu::TextureView {
+        &self.surface_view
+    }
+
     pub fn queue(&self) -> &wgpu::Queue {
-        &self.queue
+        self.queue
     }
 
-    pub fn depth_view(&self) -> &wgpu::TextureView {
-        &self.depth_view
+    /// `None` when the renderer was built without a depth buffer
+    pub fn depth_view(&self) -> Option<&wgpu::TextureView> {
+        self.depth_view
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.output.texture.width(), self.output.texture.height())
+    }
+
+    /// Load operations for the color and depth targets. The first pass of the
+    /// frame clears them, later passes keep what earlier ones drew so several
+    /// systems can share the frame.
+    pub fn load_ops(&mut self) -> (wgpu::LoadOp<wgpu::Color>, wgpu::LoadOp<f32>) {
+        if std::mem::replace(&mut self.cleared, true) {
+            (wgpu::LoadOp::Load, wgpu::LoadOp::Load)
+        } else {
+            let color_load = match self.clear_color {
+                Some(color) => wgpu::LoadOp::Clear(color),
+                None => wgpu::LoadOp::Load,
+            };
+
+            (color_load, wgpu::LoadOp::Clear(1.0))
+        }
     }
 
     pub fn encoder_mut(&mut self) -> &mut wgpu::CommandEncoder {
         &mut self.encoder
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // None on machines without any adapter, where the GPU tests are skipped
+    fn headless_adapter() -> Option<wgpu::Adapter> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))
+        .ok()
+    }
+
+    #[test]
+    fn negotiate_limits_rejects_oversized_buffers() {
+        let supported = wgpu::Limits::default();
+        let too_large = supported.max_storage_buffer_binding_size as u64 + 1;
+
+        assert!(matches!(
+            Renderer::negotiate_limits(&supported, too_large),
+            Err(RendererError::StorageBufferTooLarge { .. })
+        ));
+        assert_eq!(
+            Renderer::negotiate_limits(&supported, 1024).unwrap(),
+            wgpu::Limits::default()
+        );
+    }
+
+    #[test]
+    fn headless_frame_targets_resize() {
+        let Some(adapter) = headless_adapter() else {
+            eprintln!("No adapter found, skipping");
+            return;
+        };
+
+        let limits = Renderer::negotiate_limits(&adapter.limits(), 1 << 20).unwrap();
+        let (sample_count, required_features) = Renderer::negotiate_sample_count(&adapter, 4);
+        assert!(sample_count == 1 || sample_count == 4);
+
+        let (device, _queue) =
+            pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+                required_features,
+                required_limits: limits,
+                ..Default::default()
+            }))
+            .unwrap();
+
+        let size = |view: &wgpu::TextureView| (view.texture().width(), view.texture().height());
+
+        let mut targets = FrameTargets::new(&device, 64, 32, sample_count, true);
+        targets.resize(&device, 200, 150);
+
+        let depth_view = targets.depth_view.as_ref().unwrap();
+        assert_eq!(size(depth_view), (200, 150));
+        assert_eq!(depth_view.texture().sample_count(), sample_count);
+        assert_eq!(size(&targets.hdr_texture), (200, 150));
+        assert_eq!(targets.hdr_texture.texture().sample_count(), 1);
+        match &targets.msaa_texture {
+            Some(msaa_texture) => {
+                assert_eq!(size(msaa_texture), (200, 150));
+                assert_eq!(msaa_texture.texture().sample_count(), sample_count);
+            }
+            None => assert_eq!(sample_count, 1),
+        }
+
+        // Built without a depth buffer or MSAA, resizing adds neither
+        let mut targets = FrameTargets::new(&device, 64, 32, 1, false);
+        targets.resize(&device, 200, 150);
+        assert!(targets.depth_view.is_none());
+        assert!(targets.msaa_texture.is_none());
+        assert_eq!(size(&targets.hdr_texture), (200, 150));
+    }
+}